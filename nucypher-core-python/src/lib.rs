@@ -1259,7 +1259,8 @@ impl NodeMetadataPayload {
         Ok(Self {
             backend: nucypher_core::NodeMetadataPayload {
                 staking_provider_address: staking_provider_address.backend,
-                domain: domain.to_string(),
+                domain: nucypher_core::Domain::new(domain)
+                    .map_err(|err| PyValueError::new_err(format!("{}", err)))?,
                 timestamp_epoch,
                 verifying_key: *verifying_key.as_ref(),
                 encrypting_key: *encrypting_key.as_ref(),
@@ -1301,7 +1302,7 @@ impl NodeMetadataPayload {
 
     #[getter]
     fn domain(&self) -> &str {
-        &self.backend.domain
+        self.backend.domain.as_ref()
     }
 
     #[getter]
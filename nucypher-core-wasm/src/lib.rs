@@ -1273,7 +1273,7 @@ impl NodeMetadataPayload {
     ) -> Result<NodeMetadataPayload, Error> {
         Ok(Self(nucypher_core::NodeMetadataPayload {
             staking_provider_address: staking_provider_address.0,
-            domain: domain.to_string(),
+            domain: nucypher_core::Domain::new(domain).map_err(map_js_err)?,
             timestamp_epoch,
             verifying_key: *verifying_key.as_ref(),
             encrypting_key: *encrypting_key.as_ref(),
@@ -1307,7 +1307,7 @@ impl NodeMetadataPayload {
 
     #[wasm_bindgen(getter)]
     pub fn domain(&self) -> String {
-        self.0.domain.clone()
+        self.0.domain.to_string()
     }
 
     #[wasm_bindgen(getter)]
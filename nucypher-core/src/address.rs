@@ -1,20 +1,57 @@
+use alloc::string::{String, ToString};
+use core::fmt;
+
 use generic_array::{
     sequence::Split,
     typenum::{U12, U20},
     GenericArray,
 };
-use serde::{Deserialize, Serialize};
+use serde::{de, Deserialize, Deserializer, Serialize, Serializer};
 use sha3::{digest::Update, Digest, Keccak256};
 use umbral_pre::{serde_bytes, PublicKey};
 
 // We could use the third-party `ethereum_types::Address` here,
 // but it has an inefficient `serde` implementation (serializes as hex instead of bytes).
-// So for simplicity we just use our own type since we only need the size check.
+// So for simplicity we just use our own type since we only need the size check
+// (and the hex representation is only used in human-readable formats, see the `serde` impls).
 // Later a conversion method can be easily defined to/from `ethereum_types::Address`.
 
+/// Errors that can happen when creating an [`Address`].
+#[derive(Debug, PartialEq, Eq)]
+pub enum AddressError {
+    /// The address has a length (in bytes) different from [`Address::SIZE`].
+    WrongLength(usize),
+    /// The hex representation of the address could not be decoded.
+    InvalidHex(String),
+    /// The hex representation of the address has mixed case,
+    /// but does not match the EIP-55 checksum.
+    BadChecksum,
+}
+
+impl fmt::Display for AddressError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::WrongLength(length) => write!(
+                f,
+                "address must be {} bytes long, got {}",
+                Address::SIZE,
+                length
+            ),
+            Self::InvalidHex(msg) => write!(f, "invalid hex: {}", msg),
+            Self::BadChecksum => write!(f, "address checksum mismatch"),
+        }
+    }
+}
+
+#[cfg(feature = "std")]
+impl std::error::Error for AddressError {}
+
 /// Represents an Ethereum address (20 bytes).
-#[derive(PartialEq, Debug, Serialize, Deserialize, Copy, Clone, PartialOrd, Eq, Ord)]
-pub struct Address(#[serde(with = "serde_bytes::as_hex")] [u8; Address::SIZE]);
+///
+/// Serialized as bytes in binary formats,
+/// and as a `0x`-prefixed EIP-55 checksummed string in human-readable ones.
+#[derive(PartialEq, Debug, Copy, Clone, PartialOrd, Eq, Ord)]
+pub struct Address([u8; Address::SIZE]);
 
 impl Address {
     /// Size of canonical Ethereum address, in bytes.
@@ -25,6 +62,75 @@ impl Address {
         Self(*bytes)
     }
 
+    /// Creates an address from a slice, checking that it has the correct length.
+    pub fn try_from_bytes(bytes: &[u8]) -> Result<Self, AddressError> {
+        <[u8; Self::SIZE]>::try_from(bytes)
+            .map(Self)
+            .map_err(|_| AddressError::WrongLength(bytes.len()))
+    }
+
+    /// Creates an address from its hex representation (with or without the `0x` prefix).
+    ///
+    /// If the hex digits are in mixed case, they are checked against the EIP-55 checksum.
+    pub fn from_hex(hex_str: &str) -> Result<Self, AddressError> {
+        let digits = hex_str.strip_prefix("0x").unwrap_or(hex_str);
+        let bytes = hex::decode(digits).map_err(|err| AddressError::InvalidHex(err.to_string()))?;
+        let address = Self::try_from_bytes(&bytes)?;
+
+        let has_lowercase = digits.chars().any(|c| c.is_ascii_lowercase());
+        let has_uppercase = digits.chars().any(|c| c.is_ascii_uppercase());
+        if has_lowercase && has_uppercase && !address.matches_checksum(digits) {
+            return Err(AddressError::BadChecksum);
+        }
+
+        Ok(address)
+    }
+
+    /// Returns the `0x`-prefixed hex representation of the address
+    /// with the EIP-55 checksum encoded in the case of the digits.
+    pub fn to_checksum_address(&self) -> String {
+        let digest = self.checksum_digest();
+        let digits: String = hex::encode(self.0)
+            .chars()
+            .enumerate()
+            .map(|(i, c)| {
+                if checksum_nibble(&digest, i) >= 8 {
+                    c.to_ascii_uppercase()
+                } else {
+                    c
+                }
+            })
+            .collect();
+        ["0x", &digits].concat()
+    }
+
+    fn checksum_digest(&self) -> [u8; 32] {
+        let lowercase = hex::encode(self.0);
+        Keccak256::new()
+            .chain(lowercase.as_bytes())
+            .finalize()
+            .into()
+    }
+
+    // Checks the case of the hex digits according to EIP-55.
+    fn matches_checksum(&self, digits: &str) -> bool {
+        let digest = self.checksum_digest();
+        digits.chars().enumerate().all(|(i, c)| {
+            if c.is_ascii_digit() {
+                true
+            } else if checksum_nibble(&digest, i) >= 8 {
+                c.is_ascii_uppercase()
+            } else {
+                c.is_ascii_lowercase()
+            }
+        })
+    }
+
+    /// Returns the raw bytes of the address.
+    pub fn to_bytes(&self) -> [u8; Self::SIZE] {
+        self.0
+    }
+
     pub(crate) fn from_public_key(pk: &PublicKey) -> Self {
         // Canonical address is the last 20 bytes of keccak256 hash
         // of the uncompressed public key (without the header, so 64 bytes in total).
@@ -37,6 +143,50 @@ impl Address {
     }
 }
 
+// Returns the nibble of the digest corresponding to the `i`-th hex digit of the address.
+// (`usize::is_multiple_of()` is not available in our MSRV)
+#[allow(clippy::manual_is_multiple_of)]
+fn checksum_nibble(digest: &[u8; 32], i: usize) -> u8 {
+    (digest[i / 2] >> (if i % 2 == 0 { 4 } else { 0 })) & 0xf
+}
+
+impl Serialize for Address {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        if serializer.is_human_readable() {
+            serializer.serialize_str(&self.to_checksum_address())
+        } else {
+            serde_bytes::as_hex::serialize(&self.0, serializer)
+        }
+    }
+}
+
+struct ChecksumAddressVisitor;
+
+impl<'de> de::Visitor<'de> for ChecksumAddressVisitor {
+    type Value = Address;
+
+    fn expecting(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "0x-prefixed hex-encoded address")
+    }
+
+    fn visit_str<E: de::Error>(self, v: &str) -> Result<Self::Value, E> {
+        if !v.starts_with("0x") {
+            return Err(E::invalid_value(de::Unexpected::Str(v), &self));
+        }
+        Address::from_hex(v).map_err(E::custom)
+    }
+}
+
+impl<'de> Deserialize<'de> for Address {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        if deserializer.is_human_readable() {
+            deserializer.deserialize_str(ChecksumAddressVisitor)
+        } else {
+            serde_bytes::as_hex::deserialize(deserializer).map(Self)
+        }
+    }
+}
+
 impl AsRef<[u8]> for Address {
     fn as_ref(&self) -> &[u8] {
         self.0.as_ref()
@@ -48,3 +198,81 @@ impl From<Address> for [u8; Address::SIZE] {
         address.0
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use alloc::format;
+
+    use super::{Address, AddressError};
+    use crate::versioning::{messagepack_deserialize, messagepack_serialize};
+
+    #[test]
+    fn from_hex() {
+        // An example from EIP-55
+        let checksummed = "0x5aAeb6053F3E94C9b9A09f33669435E7Ef1BeAed";
+        let address = Address::from_hex(checksummed).unwrap();
+        assert_eq!(
+            address.to_bytes().as_ref(),
+            hex::decode(&checksummed[2..]).unwrap()
+        );
+
+        assert_eq!(Address::from_hex(&checksummed.to_lowercase()), Ok(address));
+        assert_eq!(
+            Address::from_hex(&checksummed[2..].to_uppercase()),
+            Ok(address)
+        );
+
+        let bad_checksum = checksummed.replace("aA", "Aa");
+        assert_eq!(
+            Address::from_hex(&bad_checksum),
+            Err(AddressError::BadChecksum)
+        );
+        assert_eq!(
+            Address::from_hex(&checksummed[..40]),
+            Err(AddressError::WrongLength(19))
+        );
+        assert!(matches!(
+            Address::from_hex("0xabc"),
+            Err(AddressError::InvalidHex(_))
+        ));
+    }
+
+    #[test]
+    fn to_checksum_address() {
+        // Examples from EIP-55
+        for checksummed in [
+            "0x5aAeb6053F3E94C9b9A09f33669435E7Ef1BeAed",
+            "0xfB6916095ca1df60bB79Ce92cE3Ea74c37c5d359",
+            "0xdbF03B407c01E7cD3CBea99509d93f8DDDC8C6FB",
+            "0xD1220A0cf47c7B9Be7A2E6BA89F429762e7b9aDb",
+        ] {
+            let address = Address::from_hex(checksummed).unwrap();
+            assert_eq!(address.to_checksum_address(), checksummed);
+        }
+    }
+
+    #[test]
+    fn serde() {
+        let checksummed = "0x5aAeb6053F3E94C9b9A09f33669435E7Ef1BeAed";
+        let address = Address::from_hex(checksummed).unwrap();
+
+        let json = serde_json::to_string(&address).unwrap();
+        assert_eq!(json, format!("\"{}\"", checksummed));
+        assert_eq!(serde_json::from_str::<Address>(&json).unwrap(), address);
+        let lowercase = format!("\"{}\"", checksummed.to_lowercase());
+        assert_eq!(
+            serde_json::from_str::<Address>(&lowercase).unwrap(),
+            address
+        );
+        let bad_checksum = json.replace("aA", "Aa");
+        assert!(serde_json::from_str::<Address>(&bad_checksum).is_err());
+        let unprefixed = json.replace("0x", "");
+        assert!(serde_json::from_str::<Address>(&unprefixed).is_err());
+
+        // Binary formats still use raw bytes
+        let bytes = messagepack_serialize(&address);
+        assert_eq!(bytes.len(), 2 + Address::SIZE);
+        assert_eq!(&bytes[2..], address.as_ref());
+        assert_eq!(messagepack_deserialize::<Address>(&bytes), Ok(address));
+    }
+}
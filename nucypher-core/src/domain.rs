@@ -0,0 +1,116 @@
+use alloc::string::String;
+use core::fmt;
+
+use serde::{Deserialize, Serialize};
+
+/// Errors that can happen when creating a [`Domain`].
+#[derive(Debug, PartialEq, Eq)]
+pub enum DomainError {
+    /// The domain is empty.
+    Empty,
+    /// The domain contains a character that is not allowed.
+    InvalidCharacter(char),
+}
+
+impl fmt::Display for DomainError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Empty => write!(f, "domain is empty"),
+            Self::InvalidCharacter(c) => write!(f, "domain contains an invalid character {:?}", c),
+        }
+    }
+}
+
+#[cfg(feature = "std")]
+impl std::error::Error for DomainError {}
+
+/// A network identifier.
+///
+/// Consists of lowercase ASCII letters, digits, `-` and `_`.
+///
+/// The domains created by older versions of the library were not restricted,
+/// so the deserialization accepts any string; use [`is_valid`](Self::is_valid)
+/// to check a deserialized domain.
+#[derive(PartialEq, Eq, Debug, Clone, Serialize, Deserialize)]
+pub struct Domain(String);
+
+impl Domain {
+    /// Creates a new domain, trimming the surrounding whitespace and lowercasing it.
+    pub fn new(domain: &str) -> Result<Self, DomainError> {
+        let domain = domain.trim().to_ascii_lowercase();
+        Self::validate(&domain)?;
+        Ok(Self(domain))
+    }
+
+    /// Returns `true` if the domain is normalized and consists only of the allowed characters
+    /// (always the case for the domains created with [`new`](Self::new)).
+    pub fn is_valid(&self) -> bool {
+        Self::validate(&self.0).is_ok()
+    }
+
+    fn validate(domain: &str) -> Result<(), DomainError> {
+        if domain.is_empty() {
+            return Err(DomainError::Empty);
+        }
+        match domain
+            .chars()
+            .find(|c| !(c.is_ascii_lowercase() || c.is_ascii_digit() || *c == '-' || *c == '_'))
+        {
+            Some(c) => Err(DomainError::InvalidCharacter(c)),
+            None => Ok(()),
+        }
+    }
+}
+
+impl TryFrom<&str> for Domain {
+    type Error = DomainError;
+
+    fn try_from(source: &str) -> Result<Self, Self::Error> {
+        Self::new(source)
+    }
+}
+
+impl AsRef<str> for Domain {
+    fn as_ref(&self) -> &str {
+        self.0.as_ref()
+    }
+}
+
+impl fmt::Display for Domain {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.0)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{Domain, DomainError};
+    use crate::versioning::{messagepack_deserialize, messagepack_serialize};
+
+    #[test]
+    fn normalization() {
+        assert_eq!(Domain::new(" Mainnet\n").unwrap().as_ref(), "mainnet");
+        assert_eq!(Domain::new(" \t"), Err(DomainError::Empty));
+        assert_eq!(
+            Domain::new("main net"),
+            Err(DomainError::InvalidCharacter(' '))
+        );
+    }
+
+    #[test]
+    fn deserialization() {
+        let domain = Domain::new("lynx").unwrap();
+        let bytes = messagepack_serialize(&domain);
+        // Serialized as a plain string
+        assert_eq!(bytes, messagepack_serialize(&"lynx"));
+        assert_eq!(messagepack_deserialize::<Domain>(&bytes).unwrap(), domain);
+
+        // Non-normalized domains are accepted as is (they are a part of signed payloads),
+        // but are reported as invalid
+        let bytes = messagepack_serialize(&"Lynx");
+        let domain = messagepack_deserialize::<Domain>(&bytes).unwrap();
+        assert_eq!(domain.as_ref(), "Lynx");
+        assert!(!domain.is_valid());
+        assert!(Domain::new("lynx").unwrap().is_valid());
+    }
+}
@@ -1,11 +1,20 @@
+use alloc::boxed::Box;
+use alloc::collections::{BTreeMap, BTreeSet};
+use alloc::format;
+use alloc::string::String;
+use alloc::vec::Vec;
 use core::fmt;
 
-use serde::{Deserialize, Serialize};
+use serde::{de::Error as _, Deserialize, Deserializer, Serialize};
 use sha3::{digest::Update, Digest, Keccak256};
 use umbral_pre::serde_bytes;
 
+use crate::address::Address;
 use crate::node_metadata::NodeMetadata;
-use crate::versioning::ProtocolObject;
+use crate::versioning::{
+    messagepack_deserialize, messagepack_serialize_with, ProtocolObject, ProtocolObjectInner,
+    StructEncoding,
+};
 
 /// An identifier of the fleet state.
 #[derive(PartialEq, Eq, Debug, Clone, Copy, Serialize, Deserialize)]
@@ -14,12 +23,23 @@ pub struct FleetStateChecksum(#[serde(with = "serde_bytes::as_hex")] [u8; 32]);
 impl FleetStateChecksum {
     /// Creates a checksum from the given list of node metadata, and, possibly,
     /// also the metadata of the requesting node.
+    ///
+    /// The checksum is calculated as Keccak-256 of the concatenated serialized metadata
+    /// ([`NodeMetadata::to_bytes`], including the header)
+    /// of all the nodes (including `this_node`, if given),
+    /// sorted by the staking provider address.
+    /// Therefore, it does not depend on the order of `other_nodes`.
     pub fn from_nodes(other_nodes: &[NodeMetadata], this_node: Option<&NodeMetadata>) -> Self {
-        let mut nodes = other_nodes.to_vec();
-        match this_node {
-            None => {}
-            Some(node) => nodes.push(node.clone()),
-        }
+        Self::from_node_iter(other_nodes, this_node)
+    }
+
+    /// Same as [`from_nodes`](Self::from_nodes), but takes the nodes from an iterator
+    /// (e.g. the values of a map of known nodes).
+    pub fn from_node_iter<'a>(
+        other_nodes: impl IntoIterator<Item = &'a NodeMetadata>,
+        this_node: Option<&'a NodeMetadata>,
+    ) -> Self {
+        let mut nodes = other_nodes.into_iter().chain(this_node).collect::<Vec<_>>();
 
         // We do not expect node metadata with equal checksum addresses,
         // so we use the unstable sort which is faster and has a lower memory profile.
@@ -37,7 +57,7 @@ impl FleetStateChecksum {
                 // so this may lead to unnecessary fleet state update.
                 // But, unlike ProtocolObject::to_bytes(), payload serialization
                 // is not standardized, so it is better not to rely on it.
-                digest.chain(&node.to_bytes())
+                digest.chain(node.to_bytes())
             })
             .finalize();
 
@@ -57,3 +77,298 @@ impl fmt::Display for FleetStateChecksum {
         write!(f, "FleetStateChecksum:{}...", hex_repr)
     }
 }
+
+/// A compact summary of a fleet state, for logging.
+#[derive(PartialEq, Eq, Debug, Clone, Copy)]
+pub struct FleetStateSummary {
+    /// The checksum of the fleet state.
+    pub checksum: FleetStateChecksum,
+    /// The number of nodes in the fleet.
+    pub nodes_count: usize,
+    /// The latest timestamp among the nodes' metadata (`None` if there are no nodes).
+    pub newest_timestamp_epoch: Option<u32>,
+}
+
+impl FleetStateSummary {
+    /// Creates a summary of the given nodes, and, possibly,
+    /// also the metadata of the requesting node
+    /// (see [`FleetStateChecksum::from_nodes`]).
+    pub fn from_nodes(other_nodes: &[NodeMetadata], this_node: Option<&NodeMetadata>) -> Self {
+        let nodes = other_nodes.iter().chain(this_node);
+        Self {
+            checksum: FleetStateChecksum::from_nodes(other_nodes, this_node),
+            nodes_count: other_nodes.len() + usize::from(this_node.is_some()),
+            newest_timestamp_epoch: nodes.map(|node| node.payload.timestamp_epoch).max(),
+        }
+    }
+}
+
+impl fmt::Display for FleetStateSummary {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "fleet[checksum={}..., nodes={}, newest=",
+            hex::encode(&self.checksum.0[..8]),
+            self.nodes_count
+        )?;
+        match self.newest_timestamp_epoch {
+            Some(timestamp_epoch) => write!(f, "{}]", timestamp_epoch),
+            None => write!(f, "none]"),
+        }
+    }
+}
+
+/// The difference between two fleet states, allowing a node to update its fleet state
+/// without receiving the metadata of the nodes it already knows about.
+///
+/// The nodes are identified by their staking provider addresses.
+///
+/// Unlike most protocol objects, it is serialized with the structs encoded as maps
+/// (including the node metadata), so that optional fields can be added
+/// without a version bump.
+#[derive(PartialEq, Debug, Clone, Serialize, Deserialize)]
+pub struct FleetStateDiff {
+    /// The metadata of the nodes that were added or updated, ordered by the address.
+    added: Vec<NodeMetadata>,
+    /// The addresses of the nodes that were removed.
+    #[serde(deserialize_with = "deserialize_removed")]
+    removed: BTreeSet<Address>,
+}
+
+// A `BTreeSet` is serialized in ascending order, but is deserialized from any sequence,
+// silently reordering it and dropping the duplicates.
+// To keep the serialization canonical, require the serialized order.
+fn deserialize_removed<'de, D>(deserializer: D) -> Result<BTreeSet<Address>, D::Error>
+where
+    D: Deserializer<'de>,
+{
+    let addresses = Vec::<Address>::deserialize(deserializer)?;
+    if addresses.windows(2).any(|pair| pair[0] >= pair[1]) {
+        return Err(D::Error::custom(
+            "the removed addresses are not in ascending order",
+        ));
+    }
+    Ok(addresses.into_iter().collect())
+}
+
+impl FleetStateDiff {
+    /// Creates the diff that turns `old_nodes` into `new_nodes`
+    /// (when passed to [`apply`](Self::apply)).
+    ///
+    /// Nodes are expected to have unique addresses within each list;
+    /// if they do not, the last node with the given address is used.
+    pub fn new(old_nodes: &[NodeMetadata], new_nodes: &[NodeMetadata]) -> Self {
+        let old_nodes = nodes_by_address(old_nodes);
+        let new_nodes = nodes_by_address(new_nodes);
+        let added = new_nodes
+            .iter()
+            .filter(|(address, node)| old_nodes.get(address) != Some(node))
+            .map(|(_address, node)| (*node).clone())
+            .collect();
+        let removed = old_nodes
+            .keys()
+            .filter(|address| !new_nodes.contains_key(address))
+            .copied()
+            .collect();
+        Self { added, removed }
+    }
+
+    /// Returns the metadata of the nodes that were added or updated, ordered by the address.
+    pub fn added(&self) -> &[NodeMetadata] {
+        &self.added
+    }
+
+    /// Returns the addresses of the nodes that were removed.
+    pub fn removed(&self) -> &BTreeSet<Address> {
+        &self.removed
+    }
+
+    /// Returns `true` if the fleet states are the same.
+    pub fn is_empty(&self) -> bool {
+        self.added.is_empty() && self.removed.is_empty()
+    }
+
+    /// Applies the diff to `nodes` (the older fleet state),
+    /// returning the newer fleet state ordered by the address.
+    ///
+    /// The nodes are not verified by this method.
+    pub fn apply(&self, nodes: &[NodeMetadata]) -> Vec<NodeMetadata> {
+        let mut nodes = nodes_by_address(nodes);
+        for address in self.removed.iter() {
+            nodes.remove(address);
+        }
+        for node in self.added.iter() {
+            nodes.insert(node.payload.staking_provider_address, node);
+        }
+        nodes.into_values().cloned().collect()
+    }
+}
+
+fn nodes_by_address(nodes: &[NodeMetadata]) -> BTreeMap<Address, &NodeMetadata> {
+    nodes
+        .iter()
+        .map(|node| (node.payload.staking_provider_address, node))
+        .collect()
+}
+
+// Ensures the serialization is canonical: the added nodes are ordered and unique,
+// and are not removed at the same time
+// (the order of the removed addresses is checked in `deserialize_removed()`).
+fn check_canonical(diff: &FleetStateDiff) -> Result<(), String> {
+    let addresses = diff
+        .added
+        .iter()
+        .map(|node| node.payload.staking_provider_address)
+        .collect::<Vec<_>>();
+    if addresses.windows(2).any(|pair| pair[0] >= pair[1]) {
+        return Err("the added nodes are not ordered by the address".into());
+    }
+    match addresses
+        .iter()
+        .find(|address| diff.removed.contains(address))
+    {
+        Some(address) => Err(format!("{:?} is both in added and removed nodes", address)),
+        None => Ok(()),
+    }
+}
+
+impl<'a> ProtocolObjectInner<'a> for FleetStateDiff {
+    fn brand() -> [u8; 4] {
+        *b"FSDf"
+    }
+
+    fn version() -> (u16, u16) {
+        (1, 0)
+    }
+
+    fn unversioned_to_bytes(&self) -> Box<[u8]> {
+        messagepack_serialize_with(&self, StructEncoding::Map)
+    }
+
+    fn unversioned_from_bytes(minor_version: u16, bytes: &[u8]) -> Option<Result<Self, String>> {
+        if minor_version == 0 {
+            Some(messagepack_deserialize(bytes))
+        } else {
+            None
+        }
+    }
+
+    fn validate(&self) -> Result<(), String> {
+        check_canonical(self)
+    }
+}
+
+impl<'a> ProtocolObject<'a> for FleetStateDiff {}
+
+#[cfg(test)]
+mod tests {
+    use alloc::collections::BTreeMap;
+    use alloc::format;
+    use alloc::vec::Vec;
+
+    use super::{FleetStateChecksum, FleetStateDiff, FleetStateSummary};
+    use crate::address::Address;
+    use crate::node_metadata::tests::make_node;
+    use crate::versioning::ProtocolObject;
+
+    #[test]
+    fn from_nodes() {
+        let mut nodes = (0..3).map(|i| make_node(i, 123)).collect::<Vec<_>>();
+
+        let checksum = FleetStateChecksum::from_nodes(&nodes[..2], Some(&nodes[2]));
+        nodes.reverse();
+        assert_eq!(FleetStateChecksum::from_nodes(&nodes, None), checksum);
+        assert_ne!(FleetStateChecksum::from_nodes(&nodes[1..], None), checksum);
+
+        let known_nodes = nodes
+            .iter()
+            .map(|node| (node.payload.staking_provider_address, node.clone()))
+            .collect::<BTreeMap<_, _>>();
+        assert_eq!(
+            FleetStateChecksum::from_node_iter(known_nodes.values(), None),
+            checksum
+        );
+        assert_eq!(
+            FleetStateChecksum::from_node_iter(nodes.iter().skip(1), Some(&nodes[0])),
+            checksum
+        );
+    }
+
+    #[test]
+    fn summary() {
+        let nodes = [make_node(0, 200), make_node(1, 100)];
+
+        let summary = FleetStateSummary::from_nodes(&nodes[1..], Some(&nodes[0]));
+        assert_eq!(summary.nodes_count, 2);
+        assert_eq!(summary.newest_timestamp_epoch, Some(200));
+        assert_eq!(
+            summary.checksum,
+            FleetStateChecksum::from_nodes(&nodes, None)
+        );
+        assert_eq!(
+            format!("{}", summary),
+            format!(
+                "fleet[checksum={}..., nodes=2, newest=200]",
+                hex::encode(&summary.checksum.as_ref()[..8])
+            )
+        );
+
+        let empty = FleetStateSummary::from_nodes(&[], None);
+        assert!(format!("{}", empty).ends_with(", nodes=0, newest=none]"));
+    }
+
+    #[test]
+    fn diff() {
+        let old_nodes = [make_node(3, 100), make_node(1, 100), make_node(2, 100)];
+        // Node 1 is unchanged, node 2 is updated, node 3 is removed, node 4 is added
+        let new_nodes = [make_node(4, 100), old_nodes[1].clone(), make_node(2, 200)];
+
+        let diff = FleetStateDiff::new(&old_nodes, &new_nodes);
+        assert_eq!(diff.added(), [new_nodes[2].clone(), new_nodes[0].clone()]);
+        assert_eq!(diff.removed(), &[Address::new(&[3; Address::SIZE])].into());
+
+        let restored = FleetStateDiff::from_bytes(&diff.to_bytes()).unwrap();
+        assert_eq!(restored, diff);
+
+        let updated = restored.apply(&old_nodes);
+        assert_eq!(
+            FleetStateChecksum::from_nodes(&updated, None),
+            FleetStateChecksum::from_nodes(&new_nodes, None)
+        );
+
+        assert!(FleetStateDiff::new(&new_nodes, &updated).is_empty());
+        assert_eq!(FleetStateDiff::new(&[], &[]).apply(&[]), []);
+
+        // Non-canonical serialization is rejected
+        let mut unordered = diff.clone();
+        unordered.added.reverse();
+        assert!(FleetStateDiff::from_bytes(&unordered.to_bytes()).is_err());
+        let mut conflicting = diff;
+        conflicting
+            .removed
+            .insert(Address::new(&[4; Address::SIZE]));
+        assert!(FleetStateDiff::from_bytes(&conflicting.to_bytes()).is_err());
+    }
+
+    #[test]
+    fn unordered_removed() {
+        let header = &FleetStateDiff::new(&[], &[]).to_bytes()[..8];
+        let make_bytes = |removed: &[u8]| {
+            let mut bytes = header.to_vec();
+            rmp::encode::write_map_len(&mut bytes, 2).unwrap();
+            rmp::encode::write_str(&mut bytes, "added").unwrap();
+            rmp::encode::write_array_len(&mut bytes, 0).unwrap();
+            rmp::encode::write_str(&mut bytes, "removed").unwrap();
+            rmp::encode::write_array_len(&mut bytes, removed.len() as u32).unwrap();
+            for i in removed {
+                rmp::encode::write_bin(&mut bytes, &[*i; Address::SIZE]).unwrap();
+            }
+            bytes
+        };
+
+        let diff = FleetStateDiff::from_bytes(&make_bytes(&[1, 3])).unwrap();
+        assert_eq!(diff.to_bytes().as_ref(), make_bytes(&[1, 3]));
+        assert!(FleetStateDiff::from_bytes(&make_bytes(&[3, 1])).is_err());
+        assert!(FleetStateDiff::from_bytes(&make_bytes(&[1, 1])).is_err());
+    }
+}
@@ -1,3 +1,4 @@
+use alloc::string::{String, ToString};
 use core::fmt;
 
 use generic_array::{sequence::Split, typenum::U16, GenericArray};
@@ -5,6 +6,29 @@ use serde::{Deserialize, Serialize};
 use sha3::{digest::Update, Digest, Keccak256};
 use umbral_pre::{serde_bytes, PublicKey};
 
+/// Errors that can happen when restoring an [`HRAC`] from its hex representation.
+#[derive(Debug, PartialEq, Eq)]
+pub enum HRACError {
+    /// The HRAC has a length (in bytes) different from [`HRAC::SIZE`].
+    WrongLength(usize),
+    /// The hex representation of the HRAC could not be decoded.
+    InvalidHex(String),
+}
+
+impl fmt::Display for HRACError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::WrongLength(length) => {
+                write!(f, "HRAC must be {} bytes long, got {}", HRAC::SIZE, length)
+            }
+            Self::InvalidHex(msg) => write!(f, "invalid hex: {}", msg),
+        }
+    }
+}
+
+#[cfg(feature = "std")]
+impl std::error::Error for HRACError {}
+
 /// "hashed resource access code".
 ///
 /// A hash of:
@@ -15,7 +39,7 @@ use umbral_pre::{serde_bytes, PublicKey};
 /// Publisher and Bob have all the information they need to construct this.
 /// Ursula does not, so we share it with her.
 #[allow(clippy::upper_case_acronyms)]
-#[derive(PartialEq, Eq, Debug, Copy, Clone, Serialize, Deserialize)]
+#[derive(PartialEq, Eq, PartialOrd, Ord, Hash, Debug, Copy, Clone, Serialize, Deserialize)]
 pub struct HRAC(#[serde(with = "serde_bytes::as_hex")] [u8; HRAC::SIZE]);
 
 impl HRAC {
@@ -23,6 +47,12 @@ impl HRAC {
     pub const SIZE: usize = 16;
 
     /// Creates a new HRAC.
+    ///
+    /// The HRAC is the first 16 bytes of the Keccak-256 digest of the concatenation of
+    /// the compressed publisher's verifying key, the compressed Bob's verifying key,
+    /// and the label.
+    /// Since the derivation is deterministic, it can be used to check that
+    /// a received object (e.g. a `TreasureMap`) belongs to the expected policy.
     pub fn new(
         publisher_verifying_key: &PublicKey,
         bob_verifying_key: &PublicKey,
@@ -38,6 +68,15 @@ impl HRAC {
         let (hrac, _rest): (GenericArray<u8, U16>, GenericArray<u8, _>) = digest.split();
         Self(hrac.into())
     }
+
+    /// Restores the HRAC from its hex representation
+    /// (as produced by the `Display` implementation).
+    pub fn from_hex(hex_repr: &str) -> Result<Self, HRACError> {
+        let bytes = hex::decode(hex_repr).map_err(|err| HRACError::InvalidHex(err.to_string()))?;
+        let bytes = <[u8; Self::SIZE]>::try_from(bytes.as_slice())
+            .map_err(|_| HRACError::WrongLength(bytes.len()))?;
+        Ok(Self(bytes))
+    }
 }
 
 impl From<[u8; HRAC::SIZE]> for HRAC {
@@ -54,7 +93,32 @@ impl AsRef<[u8]> for HRAC {
 
 impl fmt::Display for HRAC {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
-        let hex_repr = hex::encode(&self.0[..8]);
-        write!(f, "HRAC:{}...", hex_repr)
+        write!(f, "{}", hex::encode(self.0))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use alloc::format;
+    use alloc::string::ToString;
+
+    use super::{HRACError, HRAC};
+
+    #[test]
+    fn hex_repr() {
+        let hrac = HRAC::from([0xab; HRAC::SIZE]);
+        let hex_repr = format!("{}", hrac);
+        assert_eq!(hex_repr, "ab".repeat(HRAC::SIZE));
+        assert_eq!(HRAC::from_hex(&hex_repr), Ok(hrac));
+
+        assert_eq!(
+            HRAC::from_hex(&hex_repr[2..]),
+            Err(HRACError::WrongLength(HRAC::SIZE - 1))
+        );
+        assert!(matches!(
+            HRAC::from_hex(&"zz".repeat(HRAC::SIZE)),
+            Err(HRACError::InvalidHex(_))
+        ));
+        assert_eq!(hrac.to_string(), hex_repr);
     }
 }
@@ -3,10 +3,12 @@ use alloc::string::String;
 use alloc::vec::Vec;
 use core::fmt;
 
+use rand_core::{CryptoRng, OsRng, RngCore};
 use serde::{Deserialize, Serialize};
 use umbral_pre::{
-    decrypt_original, encrypt, serde_bytes, Capsule, DecryptionError as UmbralDecryptionError,
-    EncryptionError, KeyFrag, PublicKey, SecretKey, Signature, Signer, VerifiedKeyFrag,
+    decrypt_original, encrypt_with_rng, serde_bytes, Capsule,
+    DecryptionError as UmbralDecryptionError, EncryptionError, KeyFrag, PublicKey, SecretKey,
+    Signature, Signer, VerifiedKeyFrag,
 };
 
 use crate::hrac::HRAC;
@@ -26,14 +28,19 @@ fn signed_message(hrac: &HRAC, kfrag: &KeyFrag) -> Vec<u8> {
 }
 
 impl AuthorizedKeyFrag {
-    fn new(signer: &Signer, hrac: &HRAC, verified_kfrag: VerifiedKeyFrag) -> Self {
+    fn new(
+        rng: &mut (impl CryptoRng + RngCore),
+        signer: &Signer,
+        hrac: &HRAC,
+        verified_kfrag: VerifiedKeyFrag,
+    ) -> Self {
         // Alice makes plain to Ursula that, upon decrypting this message,
         // this particular KFrag is authorized for use in the policy identified by this HRAC.
 
         // TODO (rust-umbral#73): add VerifiedKeyFrag::unverify()?
         let kfrag = verified_kfrag.unverify();
 
-        let signature = signer.sign(&signed_message(hrac, &kfrag));
+        let signature = signer.sign_with_rng(rng, &signed_message(hrac, &kfrag));
 
         Self { signature, kfrag }
     }
@@ -76,15 +83,23 @@ impl<'a> ProtocolObjectInner<'a> for AuthorizedKeyFrag {
 
 impl<'a> ProtocolObject<'a> for AuthorizedKeyFrag {}
 
+/// Errors that can happen when decrypting an object encrypted together with
+/// the signature authorizing it: an [`EncryptedKeyFrag`],
+/// an [`EncryptedTreasureMap`](crate::EncryptedTreasureMap),
+/// or an [`EncryptedMessage`](crate::EncryptedMessage).
 #[allow(clippy::enum_variant_names)]
 #[derive(Debug)]
-pub enum DecryptionError {
+pub enum AuthorizedDecryptionError {
+    /// The ciphertext could not be decrypted with the given secret key.
     DecryptionFailed(UmbralDecryptionError),
+    /// The decrypted plaintext is not a valid serialized object.
     DeserializationFailed(DeserializationError),
+    /// The signature authorizing the object did not verify
+    /// (for a key frag, it is the publisher's signature bound to the HRAC).
     VerificationFailed,
 }
 
-impl fmt::Display for DecryptionError {
+impl fmt::Display for AuthorizedDecryptionError {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
         match self {
             Self::DecryptionFailed(err) => write!(f, "decryption failed: {}", err),
@@ -94,8 +109,15 @@ impl fmt::Display for DecryptionError {
     }
 }
 
+#[cfg(feature = "std")]
+impl std::error::Error for AuthorizedDecryptionError {}
+
 /// Encrypted and signed key frag.
-#[derive(PartialEq, Debug, Clone, Serialize, Deserialize)]
+///
+/// The publisher's signature is encrypted together with the key frag,
+/// so it can only be verified by the recipient, as a part of [`decrypt`](Self::decrypt).
+#[derive(PartialEq, Clone, Serialize, Deserialize)]
+#[cfg_attr(not(feature = "redacted-debug"), derive(Debug))]
 pub struct EncryptedKeyFrag {
     capsule: Capsule,
     #[serde(with = "serde_bytes::as_base64")]
@@ -104,43 +126,87 @@ pub struct EncryptedKeyFrag {
 
 impl EncryptedKeyFrag {
     /// Encrypts and signs a key frag.
+    ///
+    /// Panics if the encryption fails; see [`try_new`](Self::try_new) for a fallible version.
     pub fn new(
         signer: &Signer,
         recipient_key: &PublicKey,
         hrac: &HRAC,
         verified_kfrag: VerifiedKeyFrag,
     ) -> Self {
-        let auth_kfrag = AuthorizedKeyFrag::new(signer, hrac, verified_kfrag);
-        // Using Umbral for asymmetric encryption here for simplicity,
-        // even though we do not plan to re-encrypt the capsule.
-        let (capsule, ciphertext) = match encrypt(recipient_key, &auth_kfrag.to_bytes()) {
+        match Self::try_new(signer, recipient_key, hrac, verified_kfrag) {
             Ok(result) => result,
             Err(err) => match err {
                 // For now this is the only error that can happen during encryption,
                 // and there's really no point in propagating it.
                 EncryptionError::PlaintextTooLarge => panic!("encryption failed - out of memory?"),
             },
-        };
-        Self {
+        }
+    }
+
+    /// Encrypts and signs a key frag, returning an error if the encryption fails.
+    pub fn try_new(
+        signer: &Signer,
+        recipient_key: &PublicKey,
+        hrac: &HRAC,
+        verified_kfrag: VerifiedKeyFrag,
+    ) -> Result<Self, EncryptionError> {
+        Self::try_new_with_rng(&mut OsRng, signer, recipient_key, hrac, verified_kfrag)
+    }
+
+    /// Same as [`try_new`](Self::try_new), but using the given RNG.
+    pub(crate) fn try_new_with_rng(
+        rng: &mut (impl CryptoRng + RngCore),
+        signer: &Signer,
+        recipient_key: &PublicKey,
+        hrac: &HRAC,
+        verified_kfrag: VerifiedKeyFrag,
+    ) -> Result<Self, EncryptionError> {
+        let auth_kfrag = AuthorizedKeyFrag::new(rng, signer, hrac, verified_kfrag);
+        // Using Umbral for asymmetric encryption here for simplicity,
+        // even though we do not plan to re-encrypt the capsule.
+        let (capsule, ciphertext) = encrypt_with_rng(rng, recipient_key, &auth_kfrag.to_bytes())?;
+        Ok(Self {
             capsule,
             ciphertext,
-        }
+        })
     }
 
     /// Decrypts and verifies a key frag.
+    ///
+    /// The key frag is signed by the publisher together with the HRAC of the policy it
+    /// was created for, so if `hrac` does not match that policy,
+    /// `AuthorizedDecryptionError::VerificationFailed` is returned.
     pub fn decrypt(
         &self,
         sk: &SecretKey,
         hrac: &HRAC,
         publisher_verifying_key: &PublicKey,
-    ) -> Result<VerifiedKeyFrag, DecryptionError> {
+    ) -> Result<VerifiedKeyFrag, AuthorizedDecryptionError> {
         let auth_kfrag_bytes = decrypt_original(sk, &self.capsule, &self.ciphertext)
-            .map_err(DecryptionError::DecryptionFailed)?;
+            .map_err(AuthorizedDecryptionError::DecryptionFailed)?;
         let auth_kfrag = AuthorizedKeyFrag::from_bytes(&auth_kfrag_bytes)
-            .map_err(DecryptionError::DeserializationFailed)?;
+            .map_err(AuthorizedDecryptionError::DeserializationFailed)?;
         auth_kfrag
             .verify(hrac, publisher_verifying_key)
-            .ok_or(DecryptionError::VerificationFailed)
+            .ok_or(AuthorizedDecryptionError::VerificationFailed)
+    }
+}
+
+impl fmt::Display for EncryptedKeyFrag {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "EncryptedKeyFrag {{ ciphertext: {} bytes }}",
+            self.ciphertext.len()
+        )
+    }
+}
+
+#[cfg(feature = "redacted-debug")]
+impl fmt::Debug for EncryptedKeyFrag {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        fmt::Display::fmt(self, f)
     }
 }
 
@@ -167,3 +233,47 @@ impl<'a> ProtocolObjectInner<'a> for EncryptedKeyFrag {
 }
 
 impl<'a> ProtocolObject<'a> for EncryptedKeyFrag {}
+
+#[cfg(test)]
+mod tests {
+    use umbral_pre::{generate_kfrags, SecretKey, Signer};
+
+    use super::{AuthorizedDecryptionError, EncryptedKeyFrag};
+    use crate::hrac::HRAC;
+
+    #[test]
+    fn decrypt() {
+        let delegating_sk = SecretKey::random();
+        let publisher = Signer::new(SecretKey::random());
+        let bob_pk = SecretKey::random().public_key();
+        let ursula_sk = SecretKey::random();
+        let hrac = HRAC::new(&publisher.verifying_key(), &bob_pk, b"label");
+        let kfrag =
+            generate_kfrags(&delegating_sk, &bob_pk, &publisher, 1, 1, true, true)[0].clone();
+
+        let ekfrag =
+            EncryptedKeyFrag::new(&publisher, &ursula_sk.public_key(), &hrac, kfrag.clone());
+
+        let decrypted = ekfrag
+            .decrypt(&ursula_sk, &hrac, &publisher.verifying_key())
+            .unwrap();
+        assert_eq!(decrypted, kfrag);
+
+        let other_hrac = HRAC::new(&publisher.verifying_key(), &bob_pk, b"other label");
+        assert!(matches!(
+            ekfrag.decrypt(&ursula_sk, &other_hrac, &publisher.verifying_key()),
+            Err(AuthorizedDecryptionError::VerificationFailed)
+        ));
+
+        let other_publisher = SecretKey::random().public_key();
+        assert!(matches!(
+            ekfrag.decrypt(&ursula_sk, &hrac, &other_publisher),
+            Err(AuthorizedDecryptionError::VerificationFailed)
+        ));
+
+        assert!(matches!(
+            ekfrag.decrypt(&SecretKey::random(), &hrac, &publisher.verifying_key()),
+            Err(AuthorizedDecryptionError::DecryptionFailed(_))
+        ));
+    }
+}
@@ -1,4 +1,9 @@
 //! A collection of objects defining the protocol for NyCypher nodes (Ursulas).
+//!
+//! The crate is `no_std` and never reads the clock: the methods depending on the current time
+//! (e.g. [`NodeMetadata::age`] or [`TreasureMap::is_expired`]) take it as a parameter
+//! (in seconds since the Unix epoch).
+//! With the `std` feature, it can be obtained with `now_epoch()`.
 
 #![doc(html_root_url = "https://docs.rs/nucypher-core")]
 #![forbid(unsafe_code)]
@@ -7,10 +12,14 @@
 
 extern crate alloc;
 
+#[cfg(feature = "std")]
+extern crate std;
+
 mod access_control;
 mod address;
 mod conditions;
 mod dkg;
+mod domain;
 mod fleet_state;
 mod hrac;
 mod key_frag;
@@ -24,31 +33,119 @@ mod threshold_message_kit;
 mod treasure_map;
 mod versioning;
 
+#[cfg(feature = "testing")]
+pub mod test_fixtures;
+
 /// Error returned by various `verify()` methods in the crate.
+#[derive(Debug)]
 pub struct VerificationError;
 
+/// The reason of a failed verification, returned by the `verify_detailed()` methods
+/// and [`MetadataResponse::verify_full`].
+#[derive(Debug, PartialEq, Eq, Clone, Copy)]
+pub enum VerificationFailure {
+    /// The object was signed by a key other than the expected one.
+    UnexpectedSigner,
+    /// The signature does not match the signed contents
+    /// (either the contents were modified, or the signature is forged).
+    InvalidSignature,
+    /// The node metadata is correctly signed, but its domain is not valid
+    /// (see [`Domain::is_valid`]).
+    InvalidDomain,
+    /// The object is correctly signed, but the node metadata at the given position
+    /// among the ones it contains fails the verification
+    /// (see [`NodeMetadata::verify_detailed`]).
+    InvalidNode(usize),
+}
+
+impl core::fmt::Display for VerificationFailure {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        match self {
+            Self::UnexpectedSigner => write!(f, "signed by an unexpected key"),
+            Self::InvalidSignature => write!(f, "invalid signature"),
+            Self::InvalidDomain => write!(f, "invalid domain"),
+            Self::InvalidNode(index) => {
+                write!(
+                    f,
+                    "the node metadata at position {} failed verification",
+                    index
+                )
+            }
+        }
+    }
+}
+
+#[cfg(feature = "std")]
+impl std::error::Error for VerificationFailure {}
+
+/// Error returned by the constructors taking a signer and an object containing
+/// a verifying key that must belong to that signer.
+#[derive(Debug, PartialEq, Eq)]
+pub struct KeyMismatch;
+
+// Only needed where the payload carries a key provided by the caller:
+// `TreasureMap` takes the publisher's key from the signer,
+// and the signed payload of `MetadataResponse` contains no key at all.
+pub(crate) fn check_signer_matches(
+    signer: &umbral_pre::Signer,
+    expected_key: &umbral_pre::PublicKey,
+) -> Result<(), KeyMismatch> {
+    if &signer.verifying_key() == expected_key {
+        Ok(())
+    } else {
+        Err(KeyMismatch)
+    }
+}
+
+/// Returns the current time in seconds since the Unix epoch, for use with the methods
+/// taking the current time as a parameter.
+///
+/// Returns 0 if the system clock is set before the epoch,
+/// and saturates at `u32::MAX` (which will be reached in 2106).
+#[cfg(feature = "std")]
+pub fn now_epoch() -> u32 {
+    std::time::SystemTime::now()
+        .duration_since(std::time::UNIX_EPOCH)
+        .map(|duration| duration.as_secs().try_into().unwrap_or(u32::MAX))
+        .unwrap_or(0)
+}
+
 pub use access_control::{encrypt_for_dkg, AccessControlPolicy, AuthenticatedData};
 
-pub use address::Address;
+pub use address::{Address, AddressError};
 pub use conditions::{Conditions, Context};
 pub use dkg::{
     session::{SessionSecretFactory, SessionSharedSecret, SessionStaticKey, SessionStaticSecret},
     DecryptionError, EncryptedThresholdDecryptionRequest, EncryptedThresholdDecryptionResponse,
     EncryptionError, ThresholdDecryptionRequest, ThresholdDecryptionResponse,
 };
-pub use fleet_state::FleetStateChecksum;
-pub use hrac::HRAC;
-pub use key_frag::EncryptedKeyFrag;
+pub use domain::{Domain, DomainError};
+pub use fleet_state::{FleetStateChecksum, FleetStateDiff, FleetStateSummary};
+pub use hrac::{HRACError, HRAC};
+pub use key_frag::{AuthorizedDecryptionError, EncryptedKeyFrag};
 pub use message_kit::MessageKit;
 pub use node_metadata::{
-    MetadataRequest, MetadataResponse, MetadataResponsePayload, NodeMetadata, NodeMetadataPayload,
+    AnnouncementStats, MetadataRequest, MetadataResponse, MetadataResponsePayload, NodeMetadata,
+    NodeMetadataPayload, NodeMetadataStub, PaginationError, ResponseMetadata,
+    VerifiedMetadataResponse, VerifiedNodeMetadata,
 };
 pub use reencryption::{ReencryptionRequest, ReencryptionResponse};
-pub use retrieval_kit::RetrievalKit;
+pub use retrieval_kit::{
+    capsule_fingerprint, BatchRetrievalKit, BatchRetrievalKitError, RetrievalKit, RetrievalKitRef,
+};
 pub use revocation_order::RevocationOrder;
 pub use threshold_message_kit::ThresholdMessageKit;
-pub use treasure_map::{EncryptedTreasureMap, TreasureMap};
-pub use versioning::ProtocolObject;
+pub use treasure_map::{
+    DeliveryPacket, EncryptedMessage, EncryptedTreasureMap, RetrievalPlan, TreasureMap,
+    TreasureMapDiff, TreasureMapError,
+};
+pub use versioning::{
+    sign_detached, verify_detached, DeserializationError, ErasedProtocolObject, ProtocolObject,
+    Versioned,
+};
+
+#[cfg(feature = "std")]
+pub use versioning::read_protocol_object;
 
 // Re-export umbral_pre so that the users don't have to version-match.
 pub use umbral_pre;
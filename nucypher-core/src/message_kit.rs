@@ -45,6 +45,55 @@ impl MessageKit {
         }
     }
 
+    /// Creates an encrypted message from its components
+    /// (e.g. the ones previously obtained from [`capsule`](Self::capsule)
+    /// and [`ciphertext`](Self::ciphertext)).
+    ///
+    /// No verification is performed until the message is decrypted.
+    pub fn from_parts(
+        capsule: Capsule,
+        ciphertext: Box<[u8]>,
+        conditions: Option<&Conditions>,
+    ) -> Self {
+        Self {
+            capsule,
+            ciphertext,
+            conditions: conditions.cloned(),
+        }
+    }
+
+    /// Returns the capsule encapsulating the symmetric key for this message.
+    pub fn capsule(&self) -> &Capsule {
+        &self.capsule
+    }
+
+    /// Returns the encrypted message.
+    pub fn ciphertext(&self) -> &[u8] {
+        &self.ciphertext
+    }
+
+    /// Returns the length of the encrypted message, in bytes.
+    pub fn ciphertext_len(&self) -> usize {
+        self.ciphertext.len()
+    }
+
+    /// Returns the number of capsules in the kit, which is the number of
+    /// re-encryption operations each Ursula has to perform for the message.
+    ///
+    /// Always 1, since a message kit contains a single capsule.
+    pub fn capsule_count(&self) -> usize {
+        1
+    }
+
+    /// Returns the total size of the encrypted data in bytes
+    /// (the capsule and the ciphertext, but not the conditions).
+    ///
+    /// Use [`serialized_size_hint`](ProtocolObject::serialized_size_hint)
+    /// for the size of the serialized kit.
+    pub fn total_size(&self) -> usize {
+        self.capsule.to_bytes_simple().len() + self.ciphertext.len()
+    }
+
     /// Decrypts the message using the original (Alice's) key.
     pub fn decrypt(&self, sk: &SecretKey) -> Result<Box<[u8]>, DecryptionError> {
         decrypt_original(sk, &self.capsule, &self.ciphertext)
@@ -90,3 +139,18 @@ impl<'a> ProtocolObjectInner<'a> for MessageKit {
 }
 
 impl<'a> ProtocolObject<'a> for MessageKit {}
+
+#[cfg(test)]
+mod tests {
+    use umbral_pre::SecretKey;
+
+    use super::MessageKit;
+
+    #[test]
+    fn sizes() {
+        let kit = MessageKit::new(&SecretKey::random().public_key(), b"message", None);
+        assert_eq!(kit.capsule_count(), 1);
+        // A capsule consists of two compressed points and a scalar.
+        assert_eq!(kit.total_size(), 33 + 33 + 32 + kit.ciphertext_len());
+    }
+}
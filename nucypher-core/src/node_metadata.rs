@@ -1,20 +1,30 @@
 use alloc::boxed::Box;
+use alloc::collections::BTreeMap;
+use alloc::format;
 use alloc::string::String;
 use alloc::string::ToString;
+use alloc::vec::Vec;
+use core::cmp::Ordering;
 use core::fmt;
 
 use ferveo::api::PublicKey as FerveoPublicKey;
-use serde::{Deserialize, Serialize};
+use rand_core::{CryptoRng, OsRng, RngCore};
+use rmp::decode;
+use serde::{de::Error as _, Deserialize, Deserializer, Serialize};
 use serde_with::serde_as;
+use sha2::Sha256;
 use sha3::{digest::Update, Digest, Keccak256};
+use subtle::ConstantTimeEq;
 use umbral_pre::{serde_bytes, PublicKey, RecoverableSignature, Signature, Signer};
 
 use crate::address::Address;
+use crate::domain::Domain;
 use crate::fleet_state::FleetStateChecksum;
 use crate::versioning::{
-    messagepack_deserialize, messagepack_serialize, ProtocolObject, ProtocolObjectInner,
+    messagepack_deserialize, messagepack_serialize, split_header, DeserializationError,
+    ProtocolObject, ProtocolObjectInner,
 };
-use crate::VerificationError;
+use crate::{check_signer_matches, KeyMismatch, VerificationError, VerificationFailure};
 
 /// Indicates an error during canonical address derivation from a signature.
 pub enum AddressDerivationError {
@@ -37,6 +47,31 @@ impl fmt::Display for AddressDerivationError {
     }
 }
 
+/// Errors that can happen when splitting a response with
+/// [`VerifiedMetadataResponse::paginate`].
+#[derive(Debug, PartialEq, Eq)]
+pub enum PaginationError {
+    /// The page size is zero.
+    ZeroPageSize,
+    /// The number of pages does not fit into `u16`.
+    TooManyPages,
+    /// The response has co-signatures, which would not cover the pages.
+    Cosigned,
+}
+
+impl fmt::Display for PaginationError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::ZeroPageSize => write!(f, "page size must be non-zero"),
+            Self::TooManyPages => write!(f, "the number of pages does not fit into u16"),
+            Self::Cosigned => write!(f, "a co-signed response cannot be paginated"),
+        }
+    }
+}
+
+#[cfg(feature = "std")]
+impl std::error::Error for PaginationError {}
+
 /// Mimics the format of `eth_account.messages.encode_defunct()` which NuCypher codebase uses.
 fn encode_defunct(message: &[u8]) -> Keccak256 {
     Keccak256::new()
@@ -54,7 +89,7 @@ pub struct NodeMetadataPayload {
     /// The staking provider's Ethereum address.
     pub staking_provider_address: Address,
     /// The network identifier.
-    pub domain: String,
+    pub domain: Domain,
     /// The timestamp of the metadata creation.
     pub timestamp_epoch: u32,
     /// The node's verifying key.
@@ -64,7 +99,14 @@ pub struct NodeMetadataPayload {
     /// Ferveo public key to use for DKG participation.
     pub ferveo_public_key: FerveoPublicKey,
     /// The node's SSL certificate (serialized in DER format).
-    #[serde(with = "serde_bytes::as_base64")]
+    // Note: the certificate is stored as DER and not PEM, so it is already a compact
+    // binary encoding, and compressing it does not give a significant size reduction.
+    // Changing the encoding would also require a major version change,
+    // since the payload is signed.
+    #[serde(
+        serialize_with = "serde_bytes::as_base64::serialize",
+        deserialize_with = "deserialize_certificate"
+    )]
     pub certificate_der: Box<[u8]>,
     /// The hostname of the node's REST service.
     pub host: String,
@@ -74,14 +116,58 @@ pub struct NodeMetadataPayload {
     pub operator_signature: RecoverableSignature,
 }
 
+fn deserialize_certificate<'de, D>(deserializer: D) -> Result<Box<[u8]>, D::Error>
+where
+    D: Deserializer<'de>,
+{
+    let certificate_der: Box<[u8]> = serde_bytes::as_base64::deserialize(deserializer)?;
+    if certificate_der.len() > NodeMetadataPayload::MAX_CERTIFICATE_SIZE {
+        return Err(D::Error::custom(DeserializationError::TooLarge {
+            limit: NodeMetadataPayload::MAX_CERTIFICATE_SIZE,
+            received: certificate_der.len(),
+        }));
+    }
+    Ok(certificate_der)
+}
+
 impl NodeMetadataPayload {
+    /// The maximum size (in bytes) of `certificate_der` accepted on deserialization.
+    ///
+    /// DER certificates used by the nodes are normally under 2 KiB.
+    pub const MAX_CERTIFICATE_SIZE: usize = 16 * 1024;
+
     // Standard payload serialization for signing purposes.
     fn to_bytes(&self) -> Box<[u8]> {
         messagepack_serialize(self)
     }
 
+    /// Returns the bytes to be signed by the node's signer
+    /// (for use with an external signer and [`NodeMetadata::from_signature`]).
+    ///
+    /// The encoding is canonical, so that other implementations
+    /// can produce and verify the signature: a MessagePack array of the fields
+    /// in the order they are declared, each encoded in the shortest form,
+    /// with the byte fields (the address, the keys, the certificate, and the signature)
+    /// encoded as `bin` (the Ferveo public key as a single-element array containing `bin`).
+    /// There are no optional fields, so the length of the array is always the same.
+    pub fn signing_bytes(&self) -> Box<[u8]> {
+        self.to_bytes()
+    }
+
+    // An upper estimate of the serialized payload size.
+    fn size_hint(&self) -> usize {
+        // The fixed-size fields (keys, signature, address etc) along with the MessagePack
+        // markers take about 300 bytes; the rest is taken by the variable-size fields.
+        512 + self.certificate_der.len() + self.domain.as_ref().len() + self.host.len()
+    }
+
     /// Derives the address corresponding to the public key that was used
     /// to create `operator_signature`.
+    ///
+    /// Note that the result is the operator address, which is different from
+    /// `staking_provider_address`; the two are only linked on-chain,
+    /// so it has to be compared with the operator bonded to the staking provider
+    /// to establish that the node is authorized to act on its behalf.
     pub fn derive_operator_address(&self) -> Result<Address, AddressDerivationError> {
         let digest = encode_defunct(&self.verifying_key.to_compressed_bytes());
         let key = PublicKey::recover_from_prehash(&digest.finalize(), &self.operator_signature)
@@ -91,7 +177,7 @@ impl NodeMetadataPayload {
 }
 
 /// Signed node metadata.
-#[derive(PartialEq, Eq, Debug, Serialize, Deserialize, Clone)]
+#[derive(Eq, Debug, Serialize, Deserialize, Clone)]
 pub struct NodeMetadata {
     signature: Signature,
     /// Authorized metadata payload.
@@ -101,14 +187,61 @@ pub struct NodeMetadata {
 impl NodeMetadata {
     /// Creates and signs a new metadata object.
     pub fn new(signer: &Signer, payload: &NodeMetadataPayload) -> Self {
-        // TODO: how can we ensure that `verifying_key` in `payload` is the same as in `signer`?
+        Self::new_with_rng(&mut OsRng, signer, payload)
+    }
+
+    /// Creates and signs a new metadata object,
+    /// checking that the `verifying_key` in `payload` belongs to `signer`
+    /// (otherwise the object would fail verification).
+    pub fn try_new(signer: &Signer, payload: &NodeMetadataPayload) -> Result<Self, KeyMismatch> {
+        check_signer_matches(signer, &payload.verifying_key)?;
+        Ok(Self::new(signer, payload))
+    }
+
+    /// Same as [`new`](Self::new), but using the given RNG.
+    pub(crate) fn new_with_rng(
+        rng: &mut (impl CryptoRng + RngCore),
+        signer: &Signer,
+        payload: &NodeMetadataPayload,
+    ) -> Self {
+        // Note: this does not check that `verifying_key` in `payload` belongs to `signer`;
+        // use `try_new()` for that.
         Self {
-            signature: signer.sign(&payload.to_bytes()),
+            signature: signer.sign_with_rng(rng, &payload.to_bytes()),
             payload: payload.clone(),
         }
     }
 
+    /// Creates a metadata object from a signature over [`NodeMetadataPayload::signing_bytes`]
+    /// produced externally (e.g. by a hardware security module).
+    ///
+    /// Returns an error if the signature cannot be verified with the payload's `verifying_key`.
+    pub fn from_signature(
+        payload: &NodeMetadataPayload,
+        signature: &Signature,
+    ) -> Result<Self, VerificationError> {
+        let metadata = Self {
+            signature: signature.clone(),
+            payload: payload.clone(),
+        };
+        if metadata.verify() {
+            Ok(metadata)
+        } else {
+            Err(VerificationError)
+        }
+    }
+
+    /// Returns the signature over [`NodeMetadataPayload::signing_bytes`]
+    /// (e.g. to store it separately and restore the object with
+    /// [`from_signature`](Self::from_signature)).
+    pub fn signature(&self) -> &Signature {
+        &self.signature
+    }
+
     /// Verifies the consistency of signed node metadata.
+    ///
+    /// Only the signature is checked; the domain is checked by
+    /// [`verify_detailed`](Self::verify_detailed) and [`into_verified`](Self::into_verified).
     pub fn verify(&self) -> bool {
         // This method returns bool and not NodeMetadataPayload,
         // because NodeMetadata can be used before verification,
@@ -120,6 +253,139 @@ impl NodeMetadata {
         self.signature
             .verify(&self.payload.verifying_key, &self.payload.to_bytes())
     }
+
+    // The domain is not checked on deserialization, to keep the metadata
+    // created by older versions of the library readable.
+    fn check(&self) -> Result<(), VerificationFailure> {
+        if !self.verify() {
+            Err(VerificationFailure::InvalidSignature)
+        } else if !self.payload.domain.is_valid() {
+            Err(VerificationFailure::InvalidDomain)
+        } else {
+            Ok(())
+        }
+    }
+
+    /// Same as [`into_verified`](Self::into_verified), but reports the reason of the failure.
+    ///
+    /// Since the metadata is signed by the key it contains,
+    /// the possible reasons are [`VerificationFailure::InvalidSignature`],
+    /// and [`VerificationFailure::InvalidDomain`] for a correctly signed metadata
+    /// with a domain that is not [valid](Domain::is_valid).
+    pub fn verify_detailed(self) -> Result<VerifiedNodeMetadata, VerificationFailure> {
+        self.check().map(|_| VerifiedNodeMetadata(self))
+    }
+
+    /// Verifies the metadata and returns it marked as verified.
+    ///
+    /// Unlike [`verify`](Self::verify), also rejects the metadata with a domain
+    /// that is not [valid](Domain::is_valid).
+    pub fn into_verified(self) -> Result<VerifiedNodeMetadata, VerificationError> {
+        self.verify_detailed().map_err(|_| VerificationError)
+    }
+
+    /// Returns `true` if the metadata was created after `previous_timestamp_epoch`
+    /// (e.g. the timestamp of the previously received metadata of the same node).
+    ///
+    /// Metadata with an equal timestamp carries no updates,
+    /// and the one with an older timestamp is either replayed or comes from a node
+    /// with a misconfigured clock, so both should be ignored.
+    /// Use [`cmp_timestamp`](Self::cmp_timestamp) to tell these cases apart.
+    pub fn is_newer_than(&self, previous_timestamp_epoch: u32) -> bool {
+        self.cmp_timestamp(previous_timestamp_epoch) == Ordering::Greater
+    }
+
+    /// Compares the timestamp of the metadata with `previous_timestamp_epoch`.
+    pub fn cmp_timestamp(&self, previous_timestamp_epoch: u32) -> Ordering {
+        self.payload.timestamp_epoch.cmp(&previous_timestamp_epoch)
+    }
+
+    /// Returns the metadata with the latest timestamp among `candidates`
+    /// (e.g. several announcements of the same node), or `None` if there are none.
+    ///
+    /// Ties are broken by comparing the serialized objects (the larger one is returned),
+    /// so that the result does not depend on the order of `candidates`.
+    /// The candidates are not verified by this method.
+    pub fn most_recent<'a>(
+        candidates: impl IntoIterator<Item = &'a NodeMetadata>,
+    ) -> Option<&'a NodeMetadata> {
+        candidates.into_iter().max_by(|node1, node2| {
+            node1
+                .payload
+                .timestamp_epoch
+                .cmp(&node2.payload.timestamp_epoch)
+                .then_with(|| node1.to_bytes().cmp(&node2.to_bytes()))
+        })
+    }
+
+    /// Returns the number of seconds elapsed between the timestamp of the metadata
+    /// and `now_epoch`.
+    ///
+    /// Returns 0 if the timestamp is in the future relative to `now_epoch`.
+    pub fn age(&self, now_epoch: u32) -> u32 {
+        now_epoch.saturating_sub(self.payload.timestamp_epoch)
+    }
+
+    /// Verifies a batch of node metadata objects.
+    ///
+    /// Returns the verification results in the same order as `nodes`.
+    ///
+    /// With the `rayon` feature enabled, the objects are verified in parallel
+    /// on the global `rayon` thread pool; otherwise the verification is sequential.
+    ///
+    /// Verification is CPU-bound, and is done synchronously.
+    /// In an async context, large batches can be split with [`slice::chunks`]
+    /// and offloaded to a blocking thread pool, yielding to the executor between chunks:
+    ///
+    /// ```
+    /// # use nucypher_core::NodeMetadata;
+    /// # fn verify_in_chunks(nodes: &[NodeMetadata]) -> Vec<bool> {
+    /// let mut results = Vec::with_capacity(nodes.len());
+    /// for chunk in nodes.chunks(64) {
+    ///     let chunk = chunk.to_vec();
+    ///     // E.g. `tokio::task::spawn_blocking()` in an async context
+    ///     let handle = std::thread::spawn(move || NodeMetadata::verify_batch(&chunk));
+    ///     results.extend(handle.join().unwrap());
+    /// }
+    /// # results
+    /// # }
+    /// ```
+    pub fn verify_batch(nodes: &[NodeMetadata]) -> Vec<bool> {
+        #[cfg(feature = "rayon")]
+        {
+            use rayon::prelude::*;
+            nodes.par_iter().map(|node| node.verify()).collect()
+        }
+
+        #[cfg(not(feature = "rayon"))]
+        nodes.iter().map(|node| node.verify()).collect()
+    }
+}
+
+/// Node metadata with a verified signature.
+#[derive(PartialEq, Eq, Debug, Clone)]
+pub struct VerifiedNodeMetadata(NodeMetadata);
+
+impl VerifiedNodeMetadata {
+    /// Returns the verified metadata.
+    pub fn metadata(&self) -> &NodeMetadata {
+        &self.0
+    }
+
+    /// Consumes the object and returns the verified metadata.
+    pub fn into_metadata(self) -> NodeMetadata {
+        self.0
+    }
+}
+
+impl PartialEq for NodeMetadata {
+    fn eq(&self, other: &Self) -> bool {
+        // The object contains a signature and keys, and can be compared
+        // against an attacker-supplied one, so we use a constant-time comparison.
+        messagepack_serialize(self)
+            .ct_eq(&messagepack_serialize(other))
+            .into()
+    }
 }
 
 impl<'a> ProtocolObjectInner<'a> for NodeMetadata {
@@ -139,6 +405,11 @@ impl<'a> ProtocolObjectInner<'a> for NodeMetadata {
         messagepack_serialize(&self)
     }
 
+    fn unversioned_size_hint(&self) -> usize {
+        // Signature and the MessagePack markers.
+        80 + self.payload.size_hint()
+    }
+
     fn unversioned_from_bytes(minor_version: u16, bytes: &[u8]) -> Option<Result<Self, String>> {
         if minor_version == 0 {
             Some(messagepack_deserialize(bytes))
@@ -150,6 +421,169 @@ impl<'a> ProtocolObjectInner<'a> for NodeMetadata {
 
 impl<'a> ProtocolObject<'a> for NodeMetadata {}
 
+/// Node metadata without the certificate, for compact announcements.
+///
+/// Signed separately from [`NodeMetadata`] by the same key.
+/// Contains a digest of the omitted certificate, so that the full metadata
+/// fetched on demand can be matched against it with [`is_stub_of`](Self::is_stub_of).
+#[derive(Eq, Debug, Serialize, Deserialize, Clone)]
+pub struct NodeMetadataStub {
+    signature: Signature,
+    payload: NodeMetadataPayload,
+    #[serde(with = "serde_bytes::as_hex")]
+    certificate_digest: [u8; 32],
+}
+
+impl NodeMetadataStub {
+    /// Creates and signs a new stub for the given payload,
+    /// checking that the `verifying_key` in `payload` belongs to `signer`.
+    pub fn try_new(signer: &Signer, payload: &NodeMetadataPayload) -> Result<Self, KeyMismatch> {
+        check_signer_matches(signer, &payload.verifying_key)?;
+        Ok(Self::new(signer, payload))
+    }
+
+    /// Creates and signs a new stub for the given payload.
+    pub fn new(signer: &Signer, payload: &NodeMetadataPayload) -> Self {
+        let certificate_digest = Self::digest(&payload.certificate_der);
+        let payload = Self::without_certificate(payload);
+        let message = Self::message_to_sign(&payload, &certificate_digest);
+        Self {
+            signature: signer.sign(&message),
+            payload,
+            certificate_digest,
+        }
+    }
+
+    // Copies the payload except for the certificate
+    // (`..payload.clone()` would copy the certificate only to drop it).
+    fn without_certificate(payload: &NodeMetadataPayload) -> NodeMetadataPayload {
+        NodeMetadataPayload {
+            staking_provider_address: payload.staking_provider_address,
+            domain: payload.domain.clone(),
+            timestamp_epoch: payload.timestamp_epoch,
+            verifying_key: payload.verifying_key,
+            encrypting_key: payload.encrypting_key,
+            ferveo_public_key: payload.ferveo_public_key,
+            certificate_der: Box::new([]),
+            host: payload.host.clone(),
+            port: payload.port,
+            operator_signature: payload.operator_signature.clone(),
+        }
+    }
+
+    fn digest(certificate_der: &[u8]) -> [u8; 32] {
+        Sha256::new().chain(certificate_der).finalize().into()
+    }
+
+    fn message_to_sign(payload: &NodeMetadataPayload, certificate_digest: &[u8; 32]) -> Vec<u8> {
+        // The message is a serialized tuple, so it is different from the one
+        // signed in `NodeMetadata` even if the certificate is empty.
+        messagepack_serialize(&(payload, certificate_digest)).into_vec()
+    }
+
+    /// Returns the metadata payload; its `certificate_der` is empty.
+    pub fn payload(&self) -> &NodeMetadataPayload {
+        &self.payload
+    }
+
+    /// Returns the SHA-256 digest of the omitted certificate.
+    pub fn certificate_digest(&self) -> &[u8; 32] {
+        &self.certificate_digest
+    }
+
+    /// Verifies the consistency of the signed stub.
+    pub fn verify(&self) -> bool {
+        self.signature.verify(
+            &self.payload.verifying_key,
+            &Self::message_to_sign(&self.payload, &self.certificate_digest),
+        )
+    }
+
+    /// Returns `true` if `metadata` has the same payload as the stub,
+    /// and its certificate matches the digest.
+    ///
+    /// Neither object is verified by this method.
+    pub fn is_stub_of(&self, metadata: &NodeMetadata) -> bool {
+        Self::without_certificate(&metadata.payload) == self.payload
+            && Self::digest(&metadata.payload.certificate_der) == self.certificate_digest
+    }
+}
+
+impl PartialEq for NodeMetadataStub {
+    fn eq(&self, other: &Self) -> bool {
+        // See the comment in `NodeMetadata::eq()`.
+        messagepack_serialize(self)
+            .ct_eq(&messagepack_serialize(other))
+            .into()
+    }
+}
+
+impl<'a> ProtocolObjectInner<'a> for NodeMetadataStub {
+    fn brand() -> [u8; 4] {
+        *b"NdSt"
+    }
+
+    fn version() -> (u16, u16) {
+        (1, 0)
+    }
+
+    fn unversioned_to_bytes(&self) -> Box<[u8]> {
+        messagepack_serialize(&self)
+    }
+
+    fn unversioned_from_bytes(minor_version: u16, bytes: &[u8]) -> Option<Result<Self, String>> {
+        if minor_version == 0 {
+            Some(messagepack_deserialize(bytes))
+        } else {
+            None
+        }
+    }
+}
+
+impl<'a> ProtocolObject<'a> for NodeMetadataStub {}
+
+// Reads the number of announced nodes from the payload of a serialized `MetadataRequest`,
+// laid out as `[fleet_state_checksum, [node, ...]]`, without deserializing the nodes.
+fn peek_request_nodes_count(mut reader: &[u8]) -> Option<usize> {
+    if decode::read_array_len(&mut reader).ok()? != 2 {
+        return None;
+    }
+    let checksum_len = decode::read_bin_len(&mut reader).ok()?;
+    reader = reader.get(checksum_len as usize..)?;
+    decode::read_array_len(&mut reader)
+        .ok()
+        .map(|count| count as usize)
+}
+
+// Reads the timestamp and the number of announced nodes from the payload
+// of a serialized `MetadataResponse`, laid out (in all the supported versions) as
+// `[signature, [timestamp_epoch, [node, ...]], ...]`, without deserializing the nodes.
+fn peek_response_payload(mut reader: &[u8]) -> Option<(u32, usize)> {
+    decode::read_array_len(&mut reader).ok()?;
+    let signature_len = decode::read_bin_len(&mut reader).ok()?;
+    reader = reader.get(signature_len as usize..)?;
+    if decode::read_array_len(&mut reader).ok()? != 2 {
+        return None;
+    }
+    let timestamp_epoch = decode::read_int(&mut reader).ok()?;
+    let announce_nodes_count = decode::read_array_len(&mut reader).ok()?;
+    Some((timestamp_epoch, announce_nodes_count as usize))
+}
+
+// Checks the number of announced nodes before the deserialization,
+// so that a payload claiming a huge array is rejected early.
+// If the count could not be read, the payload is malformed,
+// and the error will be reported by the deserializer.
+fn check_nodes_count(count: Option<usize>, limit: usize) -> Result<(), DeserializationError> {
+    match count {
+        Some(count) if count > limit => Err(DeserializationError::TooLarge {
+            limit,
+            received: count,
+        }),
+        _ => Ok(()),
+    }
+}
+
 /// A request for metadata exchange.
 #[derive(PartialEq, Eq, Debug, Serialize, Deserialize, Clone)]
 pub struct MetadataRequest {
@@ -160,6 +594,22 @@ pub struct MetadataRequest {
 }
 
 impl MetadataRequest {
+    /// The maximum number of announced nodes accepted by [`from_bytes`](ProtocolObject::from_bytes).
+    pub const MAX_ANNOUNCE_NODES: usize = 4096;
+
+    /// Deserializes the request, accepting up to `max_announce_nodes` announced nodes
+    /// instead of [`MAX_ANNOUNCE_NODES`](Self::MAX_ANNOUNCE_NODES)
+    /// (e.g. for requests from trusted nodes).
+    pub fn from_bytes_with_limit(
+        bytes: &[u8],
+        max_announce_nodes: usize,
+    ) -> Result<Self, DeserializationError> {
+        let (_minor_version, payload) = split_header::<Self>(bytes)?;
+        check_nodes_count(peek_request_nodes_count(payload), max_announce_nodes)?;
+        messagepack_deserialize(payload)
+            .map_err(|error_msg| DeserializationError::BadPayload { error_msg })
+    }
+
     /// Creates a new request.
     pub fn new(fleet_state_checksum: &FleetStateChecksum, announce_nodes: &[NodeMetadata]) -> Self {
         Self {
@@ -167,6 +617,72 @@ impl MetadataRequest {
             announce_nodes: announce_nodes.to_vec().into_boxed_slice(),
         }
     }
+
+    /// Computes the fleet state checksum over the announced nodes
+    /// and, possibly, the metadata of the requesting node
+    /// (see [`FleetStateChecksum::from_nodes`]).
+    ///
+    /// Can be compared with `fleet_state_checksum` to detect an inconsistency
+    /// between the claimed fleet state and the attached node list.
+    /// If the requester's checksum includes its own metadata,
+    /// which is not among the announced nodes, it must be passed as `this_node`.
+    pub fn announced_fleet_checksum(&self, this_node: Option<&NodeMetadata>) -> FleetStateChecksum {
+        FleetStateChecksum::from_nodes(&self.announce_nodes, this_node)
+    }
+
+    /// Returns a copy of the request keeping only the announced nodes that pass verification
+    /// (the fleet state checksum is preserved),
+    /// and the number of the nodes that were dropped.
+    pub fn filtered(&self) -> (MetadataRequest, usize) {
+        let announce_nodes = self
+            .announce_nodes
+            .iter()
+            .filter(|node| node.verify())
+            .cloned()
+            .collect::<Box<[_]>>();
+        let dropped_count = self.announce_nodes.len() - announce_nodes.len();
+        let request = Self {
+            fleet_state_checksum: self.fleet_state_checksum,
+            announce_nodes,
+        };
+        (request, dropped_count)
+    }
+
+    /// Returns the statistics of the announced nodes
+    /// (e.g. to score the sender of a relayed request).
+    ///
+    /// Each node is verified, and only the verified nodes are checked for the domain,
+    /// since the domain of an unverified node cannot be trusted.
+    /// `expected_domain` is normalized the same way as in [`Domain::new`];
+    /// if it is not a valid domain, no nodes are counted as being on it.
+    pub fn announcement_stats(&self, expected_domain: &str) -> AnnouncementStats {
+        let expected_domain = Domain::new(expected_domain).ok();
+        let verified_nodes = self
+            .announce_nodes
+            .iter()
+            .filter(|node| node.verify())
+            .collect::<Vec<_>>();
+        AnnouncementStats {
+            total: self.announce_nodes.len(),
+            verified: verified_nodes.len(),
+            on_domain: verified_nodes
+                .iter()
+                .filter(|node| Some(&node.payload.domain) == expected_domain.as_ref())
+                .count(),
+        }
+    }
+}
+
+/// The statistics of the nodes announced in a [`MetadataRequest`]
+/// (see [`MetadataRequest::announcement_stats`]).
+#[derive(PartialEq, Eq, Debug, Clone, Copy)]
+pub struct AnnouncementStats {
+    /// The number of announced nodes.
+    pub total: usize,
+    /// The number of announced nodes with a valid signature.
+    pub verified: usize,
+    /// The number of announced nodes with a valid signature and the expected domain.
+    pub on_domain: usize,
 }
 
 impl<'a> ProtocolObjectInner<'a> for MetadataRequest {
@@ -182,13 +698,33 @@ impl<'a> ProtocolObjectInner<'a> for MetadataRequest {
         messagepack_serialize(&self)
     }
 
+    fn unversioned_size_hint(&self) -> usize {
+        // Fleet state checksum and the MessagePack markers.
+        48 + self
+            .announce_nodes
+            .iter()
+            .map(|node| node.unversioned_size_hint())
+            .sum::<usize>()
+    }
+
     fn unversioned_from_bytes(minor_version: u16, bytes: &[u8]) -> Option<Result<Self, String>> {
         if minor_version == 0 {
-            Some(messagepack_deserialize(bytes))
+            Some(
+                check_nodes_count(peek_request_nodes_count(bytes), Self::MAX_ANNOUNCE_NODES)
+                    .map_err(|err| format!("{}", err))
+                    .and_then(|_| messagepack_deserialize(bytes)),
+            )
         } else {
             None
         }
     }
+
+    fn validate(&self) -> Result<(), String> {
+        // Already checked before the deserialization in `unversioned_from_bytes()`,
+        // but the payload may come from a different encoding.
+        check_nodes_count(Some(self.announce_nodes.len()), Self::MAX_ANNOUNCE_NODES)
+            .map_err(|err| format!("{}", err))
+    }
 }
 
 impl<'a> ProtocolObject<'a> for MetadataRequest {}
@@ -212,26 +748,246 @@ impl MetadataResponsePayload {
         }
     }
 
+    /// Creates the new metadata response payload taking the ownership of the nodes
+    /// (avoiding an intermediate collection if they are filtered from a larger set).
+    pub fn from_nodes(
+        timestamp_epoch: u32,
+        announce_nodes: impl IntoIterator<Item = NodeMetadata>,
+    ) -> Self {
+        Self {
+            timestamp_epoch,
+            announce_nodes: announce_nodes.into_iter().collect(),
+        }
+    }
+
+    /// Returns the number of seconds elapsed between the fleet state timestamp and `now_epoch`.
+    ///
+    /// Returns 0 if the timestamp is in the future relative to `now_epoch`.
+    pub fn age(&self, now_epoch: u32) -> u32 {
+        now_epoch.saturating_sub(self.timestamp_epoch)
+    }
+
     // Standard payload serialization for signing purposes.
     fn to_bytes(&self) -> Box<[u8]> {
         messagepack_serialize(self)
     }
+
+    /// Returns the bytes to be signed by the responding node's signer
+    /// (for use with an external signer and [`MetadataResponse::from_signature`]).
+    pub fn signing_bytes(&self) -> Box<[u8]> {
+        self.to_bytes()
+    }
+}
+
+/// The information about a [`MetadataResponse`] available without deserializing it.
+#[derive(PartialEq, Eq, Debug, Clone, Copy)]
+pub struct ResponseMetadata {
+    /// The version `(major, minor)` of the serialized response.
+    pub version: (u16, u16),
+    /// The timestamp of the fleet state (see [`MetadataResponsePayload::timestamp_epoch`]).
+    pub timestamp_epoch: u32,
+    /// The number of announced nodes.
+    pub announce_nodes_count: usize,
 }
 
+// Prepended to the signed message of a page, so that the signature of a page
+// cannot be passed off as the one of an unpaginated response with the same nodes.
+const RESPONSE_PAGE_SIGNATURE_TAG: &[u8] = b"MRespPage";
+
 /// A response returned by an Ursula containing known node metadata.
-#[derive(PartialEq, Eq, Debug, Serialize, Deserialize, Clone)]
+#[derive(Eq, Debug, Serialize, Deserialize, Clone)]
 pub struct MetadataResponse {
     signature: Signature,
     payload: MetadataResponsePayload,
+    // Not covered by the signature; absent in the objects of version 3.0.
+    #[serde(default)]
+    signer_verifying_key: Option<PublicKey>,
+    // Not covered by the signature; absent in the objects of versions 3.0 and 3.1.
+    #[serde(default)]
+    cosignatures: Vec<(PublicKey, Signature)>,
+    // The page number and the total number of pages.
+    // Covered by the signature (see `message_to_sign()`);
+    // absent in the objects of versions 3.0 to 3.2.
+    #[serde(default)]
+    page: Option<(u16, u16)>,
 }
 
 impl MetadataResponse {
+    /// The maximum number of announced nodes accepted by [`from_bytes`](ProtocolObject::from_bytes).
+    pub const MAX_ANNOUNCE_NODES: usize = 4096;
+
+    /// Deserializes the response, accepting up to `max_announce_nodes` announced nodes
+    /// instead of [`MAX_ANNOUNCE_NODES`](Self::MAX_ANNOUNCE_NODES)
+    /// (e.g. for responses from trusted nodes).
+    pub fn from_bytes_with_limit(
+        bytes: &[u8],
+        max_announce_nodes: usize,
+    ) -> Result<Self, DeserializationError> {
+        // All the supported minor versions are deserialized the same way.
+        let (_minor_version, payload) = split_header::<Self>(bytes)?;
+        check_nodes_count(
+            peek_response_payload(payload).map(|(_, count)| count),
+            max_announce_nodes,
+        )?;
+        messagepack_deserialize(payload)
+            .map_err(|error_msg| DeserializationError::BadPayload { error_msg })
+    }
+
     /// Creates and signs a new metadata response.
     pub fn new(signer: &Signer, payload: &MetadataResponsePayload) -> Self {
+        Self::from_payload(signer, payload.clone(), None)
+    }
+
+    // Takes the payload by value, so that the callers constructing it
+    // do not have to clone it again.
+    fn from_payload(
+        signer: &Signer,
+        payload: MetadataResponsePayload,
+        page: Option<(u16, u16)>,
+    ) -> Self {
         Self {
-            signature: signer.sign(&payload.to_bytes()),
+            signature: signer.sign(&Self::message_to_sign(&payload, page)),
+            payload,
+            signer_verifying_key: None,
+            cosignatures: Vec::new(),
+            page,
+        }
+    }
+
+    // An unpaginated response signs the payload alone, as the older versions did;
+    // a page also signs its number and the total number of pages.
+    fn message_to_sign(payload: &MetadataResponsePayload, page: Option<(u16, u16)>) -> Box<[u8]> {
+        match page {
+            None => payload.to_bytes(),
+            Some((page, total_pages)) => [
+                RESPONSE_PAGE_SIGNATURE_TAG,
+                &page.to_be_bytes(),
+                &total_pages.to_be_bytes(),
+                &payload.to_bytes(),
+            ]
+            .concat()
+            .into(),
+        }
+    }
+
+    /// Creates a metadata response from a signature over
+    /// [`MetadataResponsePayload::signing_bytes`] produced externally
+    /// (e.g. by a hardware security module).
+    ///
+    /// Returns an error if the signature cannot be verified with `signer_verifying_key`.
+    pub fn from_signature(
+        payload: &MetadataResponsePayload,
+        signature: &Signature,
+        signer_verifying_key: &PublicKey,
+    ) -> Result<Self, VerificationError> {
+        if !signature.verify(signer_verifying_key, &payload.to_bytes()) {
+            return Err(VerificationError);
+        }
+        Ok(Self {
+            signature: signature.clone(),
             payload: payload.clone(),
+            signer_verifying_key: None,
+            cosignatures: Vec::new(),
+            page: None,
+        })
+    }
+
+    /// Attaches the verifying key of the signer to the response,
+    /// so that the receiver can tell a response from an unexpected node
+    /// from a corrupted one (see [`verify_detailed`](Self::verify_detailed)).
+    ///
+    /// The key is not attached by default, since it makes the response serialize
+    /// as version 3.1, which the nodes running older versions of the library cannot read.
+    pub fn with_signer_verifying_key(mut self, signer_verifying_key: &PublicKey) -> Self {
+        self.signer_verifying_key = Some(*signer_verifying_key);
+        self
+    }
+
+    /// Returns the verifying key of the signer, as claimed by the sender of the response
+    /// (`None` if it was not attached with
+    /// [`with_signer_verifying_key`](Self::with_signer_verifying_key)).
+    ///
+    /// The key is not authenticated, so it can only be used to check
+    /// that the response is being verified against the expected key,
+    /// and not as a replacement for the trusted key passed to [`verify`](Self::verify).
+    pub fn signer_verifying_key(&self) -> Option<&PublicKey> {
+        self.signer_verifying_key.as_ref()
+    }
+
+    /// Returns the number of the page, starting from 0,
+    /// if the response was created by [`VerifiedMetadataResponse::paginate`]
+    /// (0 otherwise).
+    ///
+    /// The page numbers are covered by the signature,
+    /// so a verified page cannot be relabeled or passed off as a complete response.
+    pub fn page(&self) -> u16 {
+        self.page.map_or(0, |(page, _)| page)
+    }
+
+    /// Returns the total number of pages,
+    /// if the response was created by [`VerifiedMetadataResponse::paginate`]
+    /// (1 otherwise).
+    ///
+    /// Covered by the signature, see [`page`](Self::page).
+    pub fn total_pages(&self) -> u16 {
+        self.page.map_or(1, |(_, total_pages)| total_pages)
+    }
+
+    /// Adds a signature of the payload made by `cosigner`
+    /// (e.g. a watcher vouching for the fleet state reported by the node).
+    ///
+    /// The co-signatures are kept in the order they were added.
+    /// Each of them covers the same message as the main signature
+    /// (the payload and, for a page, its number), so they can be added independently.
+    pub fn cosign(mut self, cosigner: &Signer) -> Self {
+        let signature = cosigner.sign(&Self::message_to_sign(&self.payload, self.page));
+        self.cosignatures
+            .push((cosigner.verifying_key(), signature));
+        self
+    }
+
+    /// Returns the keys of the co-signers, in the order the co-signatures were added.
+    ///
+    /// The keys are not authenticated; use [`verify_all`](Self::verify_all) to check them.
+    pub fn cosigning_keys(&self) -> Vec<PublicKey> {
+        self.cosignatures.iter().map(|(key, _)| *key).collect()
+    }
+
+    /// Returns `true` if for each of `required_keys` the payload has a valid signature
+    /// (either the main one or a co-signature) made with that key.
+    ///
+    /// An empty `required_keys` is trivially satisfied,
+    /// so the main signer's key must be included if it is to be checked.
+    pub fn verify_all(&self, required_keys: &[PublicKey]) -> bool {
+        let message = Self::message_to_sign(&self.payload, self.page);
+        required_keys.iter().all(|required_key| {
+            self.signature.verify(required_key, &message)
+                || self.cosignatures.iter().any(|(key, signature)| {
+                    key == required_key && signature.verify(required_key, &message)
+                })
+        })
+    }
+
+    /// Reads the header and the fleet state information of a serialized response
+    /// without deserializing the announced nodes.
+    ///
+    /// Since the signature is not verified, the result can only be used
+    /// to reject responses early (e.g. the stale or the oversized ones).
+    /// Returns `None` if `bytes` do not start with a supported version of a `MetadataResponse`.
+    pub fn peek_metadata(bytes: &[u8]) -> Option<ResponseMetadata> {
+        let version = Self::version_of(bytes)?;
+        if version.0 != <Self as ProtocolObject>::version().0
+            || !<Self as ProtocolObject>::supported_minor_versions().contains(&version.1)
+        {
+            return None;
         }
+
+        let (timestamp_epoch, announce_nodes_count) = peek_response_payload(&bytes[8..])?;
+        Some(ResponseMetadata {
+            version,
+            timestamp_epoch,
+            announce_nodes_count,
+        })
     }
 
     /// Verifies the metadata response and returns the contained payload.
@@ -239,17 +995,212 @@ impl MetadataResponse {
         self,
         verifying_pk: &PublicKey,
     ) -> Result<MetadataResponsePayload, VerificationError> {
-        if self
-            .signature
-            .verify(verifying_pk, &self.payload.to_bytes())
+        self.into_verified(verifying_pk)
+            .map(|response| response.into_payload())
+    }
+
+    /// Same as [`into_verified`](Self::into_verified), but reports the reason of the failure.
+    ///
+    /// If the signature cannot be verified, and the response contains
+    /// a [`signer_verifying_key`](Self::signer_verifying_key) different from `verifying_pk`,
+    /// [`VerificationFailure::UnexpectedSigner`] is returned
+    /// (e.g. the response came from a node other than the expected one);
+    /// otherwise it is [`VerificationFailure::InvalidSignature`].
+    pub fn verify_detailed(
+        self,
+        verifying_pk: &PublicKey,
+    ) -> Result<VerifiedMetadataResponse, VerificationFailure> {
+        // The claimed key is not authenticated, so it is only used to diagnose a failure.
+        let unexpected_signer =
+            matches!(self.signer_verifying_key, Some(key) if &key != verifying_pk);
+        self.into_verified(verifying_pk).map_err(|_| {
+            if unexpected_signer {
+                VerificationFailure::UnexpectedSigner
+            } else {
+                VerificationFailure::InvalidSignature
+            }
+        })
+    }
+
+    /// Verifies the metadata response and each of the announced nodes.
+    ///
+    /// Returns [`VerificationFailure::InvalidNode`] with the position of the first node
+    /// that fails [`NodeMetadata::verify_detailed`] (an invalid signature or domain),
+    /// if there is one.
+    /// To accept the response dropping the invalid nodes instead,
+    /// use [`into_verified`](Self::into_verified)
+    /// followed by [`VerifiedMetadataResponse::verified_nodes`].
+    pub fn verify_full(
+        self,
+        responder_key: &PublicKey,
+    ) -> Result<VerifiedMetadataResponse, VerificationFailure> {
+        let response = self.verify_detailed(responder_key)?;
+        match response
+            .payload()
+            .announce_nodes
+            .iter()
+            .position(|node| node.check().is_err())
         {
-            Ok(self.payload)
+            Some(index) => Err(VerificationFailure::InvalidNode(index)),
+            None => Ok(response),
+        }
+    }
+
+    /// Verifies the metadata response and returns it marked as verified.
+    ///
+    /// The payload is moved, not copied, into the returned object.
+    pub fn into_verified(
+        self,
+        verifying_pk: &PublicKey,
+    ) -> Result<VerifiedMetadataResponse, VerificationError> {
+        if self.signature.verify(
+            verifying_pk,
+            &Self::message_to_sign(&self.payload, self.page),
+        ) {
+            Ok(VerifiedMetadataResponse(self))
         } else {
             Err(VerificationError)
         }
     }
 }
 
+/// A metadata response with a verified signature.
+#[derive(PartialEq, Eq, Debug, Clone)]
+pub struct VerifiedMetadataResponse(MetadataResponse);
+
+impl VerifiedMetadataResponse {
+    /// Returns the verified payload.
+    pub fn payload(&self) -> &MetadataResponsePayload {
+        &self.0.payload
+    }
+
+    /// Consumes the response and returns the verified payload.
+    pub fn into_payload(self) -> MetadataResponsePayload {
+        self.0.payload
+    }
+
+    /// Returns `true` if the response does not announce any nodes.
+    ///
+    /// Such a response is valid, but may indicate a misbehaving peer,
+    /// so it is up to the caller to decide how to treat it.
+    pub fn is_empty(&self) -> bool {
+        self.0.payload.announce_nodes.is_empty()
+    }
+
+    /// Returns the original signed response (e.g. to relay it to other nodes
+    /// without re-signing it).
+    pub fn response(&self) -> &MetadataResponse {
+        &self.0
+    }
+
+    /// Consumes the object and returns the original signed response.
+    pub fn into_response(self) -> MetadataResponse {
+        self.0
+    }
+
+    /// Computes the fleet state checksum over the announced nodes
+    /// and, possibly, the metadata of the observing node
+    /// (see [`FleetStateChecksum::from_nodes`]).
+    pub fn fleet_checksum(&self, this_node: Option<&NodeMetadata>) -> FleetStateChecksum {
+        FleetStateChecksum::from_nodes(&self.0.payload.announce_nodes, this_node)
+    }
+
+    /// Verifies each of the announced nodes.
+    ///
+    /// Returns the nodes that passed the verification,
+    /// and the number of the ones that did not.
+    pub fn verified_nodes(&self) -> (Vec<VerifiedNodeMetadata>, usize) {
+        let nodes = &self.0.payload.announce_nodes;
+        let verified = nodes
+            .iter()
+            .cloned()
+            .filter_map(|node| node.into_verified().ok())
+            .collect::<Vec<_>>();
+        let rejected_count = nodes.len() - verified.len();
+        (verified, rejected_count)
+    }
+
+    /// Splits the response into pages of at most `page_size` nodes,
+    /// each of them signed by `signer` (normally, the signer of the original response)
+    /// and carrying its [`page`](MetadataResponse::page)
+    /// and [`total_pages`](MetadataResponse::total_pages).
+    ///
+    /// All the pages have the timestamp of the original payload,
+    /// so the receiver can merge the pages belonging to the same fleet state.
+    /// The page numbers are signed together with the payload
+    /// (but are not a part of it, since adding fields to it would require
+    /// a major version change).
+    /// The pages keep the [`signer_verifying_key`](MetadataResponse::signer_verifying_key)
+    /// of the original response.
+    /// If the nodes fit into a single page, the response is returned as is
+    /// (so that it stays readable by the nodes running older versions of the library).
+    ///
+    /// Returns an error if `page_size` is 0, if the number of pages does not fit into `u16`,
+    /// or if the response has to be split but has co-signatures
+    /// (they cover the original payload only; the pages can be co-signed individually).
+    pub fn paginate(
+        self,
+        signer: &Signer,
+        page_size: usize,
+    ) -> Result<Vec<VerifiedMetadataResponse>, PaginationError> {
+        if page_size == 0 {
+            return Err(PaginationError::ZeroPageSize);
+        }
+        let response = &self.0;
+        let payload = &response.payload;
+        if payload.announce_nodes.len() <= page_size {
+            return Ok([self].into());
+        }
+        if !response.cosignatures.is_empty() {
+            return Err(PaginationError::Cosigned);
+        }
+        let chunks = payload.announce_nodes.chunks(page_size);
+        let total_pages = u16::try_from(chunks.len()).map_err(|_| PaginationError::TooManyPages)?;
+        Ok(chunks
+            .zip(0..)
+            .map(|(nodes, page)| {
+                let mut page_response = MetadataResponse::from_payload(
+                    signer,
+                    MetadataResponsePayload::new(payload.timestamp_epoch, nodes),
+                    Some((page, total_pages)),
+                );
+                page_response.signer_verifying_key = response.signer_verifying_key;
+                Self(page_response)
+            })
+            .collect())
+    }
+
+    /// Unites the nodes announced in `responses` (e.g. received from several Ursulas),
+    /// keeping only the most recent metadata for each staking provider address
+    /// (see [`NodeMetadata::most_recent`]).
+    ///
+    /// The result is ordered by the staking provider address.
+    /// The announced nodes are not verified by this method.
+    pub fn merge_all(responses: &[VerifiedMetadataResponse]) -> Vec<NodeMetadata> {
+        let mut merged = BTreeMap::<Address, &NodeMetadata>::new();
+        for node in responses
+            .iter()
+            .flat_map(|response| response.0.payload.announce_nodes.iter())
+        {
+            let address = node.payload.staking_provider_address;
+            let candidates = merged.get(&address).copied().into_iter().chain([node]);
+            if let Some(most_recent) = NodeMetadata::most_recent(candidates) {
+                merged.insert(address, most_recent);
+            }
+        }
+        merged.into_values().cloned().collect()
+    }
+}
+
+impl PartialEq for MetadataResponse {
+    fn eq(&self, other: &Self) -> bool {
+        // See the comment in `NodeMetadata::eq()`.
+        messagepack_serialize(self)
+            .ct_eq(&messagepack_serialize(other))
+            .into()
+    }
+}
+
 impl<'a> ProtocolObjectInner<'a> for MetadataResponse {
     fn brand() -> [u8; 4] {
         *b"MdRs"
@@ -261,20 +1212,750 @@ impl<'a> ProtocolObjectInner<'a> for MetadataResponse {
         // since the whole payload is signed (so we can't just substitute the default).
         // Alternatively, one can add new fields to `NodeMetadata` itself
         // (but then they won't be signed).
-        (3, 0)
+        (3, 3)
     }
 
     fn unversioned_to_bytes(&self) -> Box<[u8]> {
-        messagepack_serialize(&self)
+        // Omit the trailing fields absent in the lower minor versions.
+        match self.minor_version() {
+            0 => messagepack_serialize(&(&self.signature, &self.payload)),
+            1 => {
+                messagepack_serialize(&(&self.signature, &self.payload, &self.signer_verifying_key))
+            }
+            2 => messagepack_serialize(&(
+                &self.signature,
+                &self.payload,
+                &self.signer_verifying_key,
+                &self.cosignatures,
+            )),
+            _ => messagepack_serialize(&self),
+        }
+    }
+
+    fn minor_version(&self) -> u16 {
+        if self.page.is_some() {
+            3
+        } else if !self.cosignatures.is_empty() {
+            2
+        } else if self.signer_verifying_key.is_some() {
+            1
+        } else {
+            0
+        }
+    }
+
+    fn unversioned_size_hint(&self) -> usize {
+        // Signature, timestamp, signer's key, and the MessagePack markers.
+        137 + self
+            .payload
+            .announce_nodes
+            .iter()
+            .map(|node| node.unversioned_size_hint())
+            .sum::<usize>()
+            // Co-signer's key, signature, and the MessagePack markers.
+            + self.cosignatures.len() * 103
+            // Page numbers and the MessagePack markers.
+            + 7
+    }
+
+    fn supported_minor_versions() -> &'static [u16] {
+        &[0, 1, 2, 3]
     }
 
     fn unversioned_from_bytes(minor_version: u16, bytes: &[u8]) -> Option<Result<Self, String>> {
-        if minor_version == 0 {
-            Some(messagepack_deserialize(bytes))
+        // Version 3.0 does not have `signer_verifying_key`, versions 3.0 and 3.1
+        // do not have `cosignatures`, and versions 3.0 to 3.2 do not have `page`;
+        // they will be set to the defaults.
+        if minor_version <= 3 {
+            Some(
+                check_nodes_count(
+                    peek_response_payload(bytes).map(|(_, count)| count),
+                    Self::MAX_ANNOUNCE_NODES,
+                )
+                .map_err(|err| format!("{}", err))
+                .and_then(|_| messagepack_deserialize(bytes)),
+            )
         } else {
             None
         }
     }
+
+    fn validate(&self) -> Result<(), String> {
+        // See the comment in `MetadataRequest::validate()`.
+        check_nodes_count(
+            Some(self.payload.announce_nodes.len()),
+            Self::MAX_ANNOUNCE_NODES,
+        )
+        .map_err(|err| format!("{}", err))
+    }
 }
 
 impl<'a> ProtocolObject<'a> for MetadataResponse {}
+
+#[cfg(test)]
+pub(crate) mod tests {
+    use alloc::boxed::Box;
+    use alloc::format;
+    use alloc::vec;
+    use alloc::vec::Vec;
+
+    use ferveo::api::Keypair as FerveoKeypair;
+    use umbral_pre::{RecoverableSignature, SecretKey, Signer};
+
+    use super::{
+        AnnouncementStats, MetadataRequest, MetadataResponse, MetadataResponsePayload,
+        NodeMetadata, NodeMetadataPayload, NodeMetadataStub, PaginationError, ResponseMetadata,
+        VerifiedMetadataResponse,
+    };
+    use crate::address::Address;
+    use crate::domain::Domain;
+    use crate::fleet_state::FleetStateChecksum;
+    use crate::versioning::{
+        messagepack_deserialize, messagepack_serialize, DeserializationError, ProtocolObject,
+    };
+    use crate::{KeyMismatch, VerificationFailure};
+
+    pub(crate) fn make_node_metadata(signer: &Signer, timestamp_epoch: u32) -> NodeMetadata {
+        NodeMetadata::new(signer, &make_payload(signer, 1, timestamp_epoch))
+    }
+
+    /// Creates a node with its own signer,
+    /// and the staking provider address consisting of `i` bytes.
+    pub(crate) fn make_node(i: u8, timestamp_epoch: u32) -> NodeMetadata {
+        let signer = Signer::new(SecretKey::random());
+        NodeMetadata::new(&signer, &make_payload(&signer, i, timestamp_epoch))
+    }
+
+    fn make_payload(signer: &Signer, i: u8, timestamp_epoch: u32) -> NodeMetadataPayload {
+        // The operator signature is not checked in these tests,
+        // so we just need it to be a valid signature.
+        let mut signature_bytes = signer.sign(b"operator").to_be_bytes().to_vec();
+        signature_bytes.push(0);
+        NodeMetadataPayload {
+            staking_provider_address: Address::new(&[i; Address::SIZE]),
+            domain: Domain::new("lynx").unwrap(),
+            timestamp_epoch,
+            verifying_key: signer.verifying_key(),
+            encrypting_key: SecretKey::random().public_key(),
+            ferveo_public_key: FerveoKeypair::random().public_key(),
+            certificate_der: Box::new([3; 1500]),
+            host: "example.com".into(),
+            port: 9151,
+            operator_signature: RecoverableSignature::try_from_be_bytes(&signature_bytes).unwrap(),
+        }
+    }
+
+    #[test]
+    fn size_hint() {
+        let signer = Signer::new(SecretKey::random());
+        let nodes = (0..3).map(|i| make_node(i, 123)).collect::<Vec<_>>();
+
+        let node = &nodes[0];
+        assert!(node.serialized_size_hint() >= node.to_bytes().len());
+
+        let response = MetadataResponse::new(&signer, &MetadataResponsePayload::new(123, &nodes));
+        assert!(response.serialized_size_hint() >= response.to_bytes().len());
+
+        let response = response.cosign(&Signer::new(SecretKey::random()));
+        assert!(response.serialized_size_hint() >= response.to_bytes().len());
+    }
+
+    #[test]
+    fn age() {
+        let node = make_node(1, 1000);
+        assert_eq!(node.age(1500), 500);
+        assert_eq!(node.age(1000), 0);
+        assert_eq!(node.age(999), 0);
+
+        let node = make_node(1, u32::MAX);
+        assert_eq!(node.age(u32::MAX), 0);
+        assert_eq!(node.age(0), 0);
+
+        let payload = MetadataResponsePayload::new(u32::MAX, &[]);
+        assert_eq!(payload.age(u32::MAX), 0);
+        assert_eq!(payload.age(1000), 0);
+        assert_eq!(MetadataResponsePayload::new(0, &[]).age(u32::MAX), u32::MAX);
+    }
+
+    #[test]
+    fn peek_metadata() {
+        let signer = Signer::new(SecretKey::random());
+        let nodes = (0..3).map(|i| make_node(i, 123)).collect::<Vec<_>>();
+        let response = MetadataResponse::new(&signer, &MetadataResponsePayload::new(456, &nodes));
+        let bytes = response.to_bytes();
+
+        let metadata = MetadataResponse::peek_metadata(&bytes).unwrap();
+        assert_eq!(
+            metadata,
+            ResponseMetadata {
+                version: (3, 0),
+                timestamp_epoch: 456,
+                announce_nodes_count: 3,
+            }
+        );
+
+        assert!(MetadataResponse::peek_metadata(&bytes[..20]).is_none());
+        assert!(MetadataResponse::peek_metadata(&nodes[0].to_bytes()).is_none());
+    }
+
+    #[test]
+    fn verified_nodes() {
+        let signer = Signer::new(SecretKey::random());
+        let mut nodes = (0..3).map(|i| make_node(i, 123)).collect::<Vec<_>>();
+        // Invalidate the signature of one of the nodes
+        nodes[1].payload.timestamp_epoch = 456;
+
+        let response = MetadataResponse::new(&signer, &MetadataResponsePayload::new(123, &nodes));
+        let verified = response.into_verified(&signer.verifying_key()).unwrap();
+
+        let (verified_nodes, rejected_count) = verified.verified_nodes();
+        assert_eq!(rejected_count, 1);
+        assert_eq!(
+            verified_nodes
+                .into_iter()
+                .map(|node| node.into_metadata())
+                .collect::<Vec<_>>(),
+            [nodes[0].clone(), nodes[2].clone()]
+        );
+    }
+
+    #[test]
+    fn filtered_request() {
+        let mut nodes = (0..3).map(|i| make_node(i, 123)).collect::<Vec<_>>();
+        // Invalidate the signature of one of the nodes
+        nodes[0].payload.port = 1234;
+
+        let checksum = FleetStateChecksum::from_nodes(&nodes, None);
+        let request = MetadataRequest::new(&checksum, &nodes);
+        let (filtered, dropped_count) = request.filtered();
+        assert_eq!(dropped_count, 1);
+        assert_eq!(filtered.fleet_state_checksum, checksum);
+        assert_eq!(filtered.announce_nodes.as_ref(), &nodes[1..]);
+    }
+
+    #[test]
+    fn announced_fleet_checksum() {
+        let nodes = (0..3).map(|i| make_node(i, 123)).collect::<Vec<_>>();
+        let checksum = FleetStateChecksum::from_nodes(&nodes[1..], Some(&nodes[0]));
+
+        // The requester's own metadata is a part of the checksum, but is not announced
+        let request = MetadataRequest::new(&checksum, &nodes[1..]);
+        assert_eq!(request.announced_fleet_checksum(Some(&nodes[0])), checksum);
+        assert_ne!(request.announced_fleet_checksum(None), checksum);
+
+        let request = MetadataRequest::new(&checksum, &nodes);
+        assert_eq!(request.announced_fleet_checksum(None), checksum);
+    }
+
+    #[test]
+    fn node_metadata_stub() {
+        let signer = Signer::new(SecretKey::random());
+        let node = make_node_metadata(&signer, 123);
+        let stub = NodeMetadataStub::new(&signer, &node.payload);
+
+        assert!(stub.verify());
+        assert!(stub.payload().certificate_der.is_empty());
+        assert!(stub.to_bytes().len() < node.to_bytes().len());
+        assert!(stub.is_stub_of(&node));
+        assert_eq!(
+            NodeMetadataStub::from_bytes(&stub.to_bytes()).unwrap(),
+            stub
+        );
+
+        let mut other_node = node.clone();
+        other_node.payload.certificate_der = Box::new([4; 1500]);
+        assert!(!stub.is_stub_of(&other_node));
+
+        let mut other_node = node;
+        other_node.payload.port = 1234;
+        assert!(!stub.is_stub_of(&other_node));
+
+        let other_stub = NodeMetadataStub::new(&Signer::new(SecretKey::random()), stub.payload());
+        assert!(!other_stub.verify());
+    }
+
+    #[test]
+    fn from_signature() {
+        let signer = Signer::new(SecretKey::random());
+        let node = make_node_metadata(&signer, 123);
+
+        let signature = signer.sign(&node.payload.signing_bytes());
+        let restored = NodeMetadata::from_signature(&node.payload, &signature).unwrap();
+        assert!(restored.verify());
+        assert_eq!(restored.payload, node.payload);
+
+        let signature = Signer::new(SecretKey::random()).sign(&node.payload.signing_bytes());
+        assert!(NodeMetadata::from_signature(&node.payload, &signature).is_err());
+
+        // The stored signature can be used to restore the same object
+        let restored = NodeMetadata::from_signature(&node.payload, node.signature()).unwrap();
+        assert_eq!(restored, node);
+    }
+
+    #[test]
+    fn response_from_signature() {
+        let signer = Signer::new(SecretKey::random());
+        let nodes = [make_node_metadata(&signer, 123)];
+        let payload = MetadataResponsePayload::new(123, &nodes);
+
+        let signature = signer.sign(&payload.signing_bytes());
+        let response =
+            MetadataResponse::from_signature(&payload, &signature, &signer.verifying_key())
+                .unwrap();
+        assert_eq!(response.verify(&signer.verifying_key()).unwrap(), payload);
+
+        let another_key = SecretKey::random().public_key();
+        assert!(MetadataResponse::from_signature(&payload, &signature, &another_key).is_err());
+    }
+
+    #[test]
+    fn try_new() {
+        let signer = Signer::new(SecretKey::random());
+        let node = make_node_metadata(&signer, 123);
+        assert!(NodeMetadata::try_new(&signer, &node.payload)
+            .unwrap()
+            .verify());
+        assert!(NodeMetadataStub::try_new(&signer, &node.payload)
+            .unwrap()
+            .verify());
+
+        let another_signer = Signer::new(SecretKey::random());
+        assert_eq!(
+            NodeMetadata::try_new(&another_signer, &node.payload),
+            Err(KeyMismatch)
+        );
+        assert!(NodeMetadataStub::try_new(&another_signer, &node.payload).is_err());
+    }
+
+    #[test]
+    fn paginate() {
+        let signer = Signer::new(SecretKey::random());
+        let nodes = (0..5).map(|i| make_node(i, 123)).collect::<Vec<_>>();
+        let payload = MetadataResponsePayload::new(456, &nodes);
+        let response = MetadataResponse::new(&signer, &payload)
+            .with_signer_verifying_key(&signer.verifying_key())
+            .into_verified(&signer.verifying_key())
+            .unwrap();
+
+        let pages = response.clone().paginate(&signer, 2).unwrap();
+        assert_eq!(pages.len(), 3);
+        let restored_nodes = pages
+            .clone()
+            .into_iter()
+            .zip(0..)
+            .flat_map(|(page, page_number)| {
+                let response = page.into_response();
+                assert_eq!(response.wire_version(), (3, 3));
+                let restored = MetadataResponse::from_bytes(&response.to_bytes()).unwrap();
+                assert_eq!(restored, response);
+                assert_eq!(restored.page(), page_number);
+                assert_eq!(restored.total_pages(), 3);
+                assert_eq!(
+                    restored.signer_verifying_key(),
+                    Some(&signer.verifying_key())
+                );
+                let page_payload = restored.verify(&signer.verifying_key()).unwrap();
+                assert_eq!(page_payload.timestamp_epoch, 456);
+                page_payload.announce_nodes.into_vec()
+            })
+            .collect::<Vec<_>>();
+        assert_eq!(restored_nodes, nodes);
+
+        // The page numbers are signed, so they cannot be changed or dropped
+        let mut relabeled = pages[0].clone().into_response();
+        relabeled.page = Some((1, 3));
+        assert!(relabeled.verify(&signer.verifying_key()).is_err());
+        let mut unpaginated = pages[0].clone().into_response();
+        unpaginated.page = None;
+        assert!(unpaginated.verify(&signer.verifying_key()).is_err());
+
+        // A page can be co-signed on its own
+        let cosigner = Signer::new(SecretKey::random());
+        let cosigned = pages[1].clone().into_response().cosign(&cosigner);
+        assert!(cosigned.verify_all(&[signer.verifying_key(), cosigner.verifying_key()]));
+
+        // A single page is returned as is
+        let pages = response.clone().paginate(&signer, 5).unwrap();
+        assert_eq!(pages.len(), 1);
+        assert_eq!(pages[0], response);
+        let single = pages[0].response();
+        assert_eq!((single.page(), single.total_pages()), (0, 1));
+        assert_eq!(single.wire_version(), (3, 1));
+
+        assert_eq!(
+            response.clone().paginate(&signer, 0),
+            Err(PaginationError::ZeroPageSize)
+        );
+        let cosigned = response
+            .into_response()
+            .cosign(&cosigner)
+            .into_verified(&signer.verifying_key())
+            .unwrap();
+        assert_eq!(
+            cosigned.paginate(&signer, 2),
+            Err(PaginationError::Cosigned)
+        );
+    }
+
+    #[test]
+    fn into_verified() {
+        let signer = Signer::new(SecretKey::random());
+        let nodes = [make_node_metadata(&signer, 123)];
+        let payload = MetadataResponsePayload::new(123, &nodes);
+        let response = MetadataResponse::new(&signer, &payload);
+
+        let another_signer = Signer::new(SecretKey::random());
+        assert!(response
+            .clone()
+            .into_verified(&another_signer.verifying_key())
+            .is_err());
+
+        let verified = response.into_verified(&signer.verifying_key()).unwrap();
+        assert_eq!(verified.payload(), &payload);
+        assert_eq!(
+            verified.fleet_checksum(None),
+            FleetStateChecksum::from_nodes(&nodes, None)
+        );
+        assert_eq!(verified.into_payload(), payload);
+    }
+
+    #[test]
+    fn signer_verifying_key() {
+        let signer = Signer::new(SecretKey::random());
+        let nodes = [make_node_metadata(&signer, 123)];
+        let payload = MetadataResponsePayload::new(123, &nodes);
+        let response = MetadataResponse::new(&signer, &payload);
+        assert_eq!(response.signer_verifying_key(), None);
+        assert_eq!(response.wire_version(), (3, 0));
+        let restored = MetadataResponse::from_bytes(&response.to_bytes()).unwrap();
+        assert_eq!(restored, response);
+
+        let response = response.with_signer_verifying_key(&signer.verifying_key());
+        assert_eq!(
+            response.signer_verifying_key(),
+            Some(&signer.verifying_key())
+        );
+        assert_eq!(response.wire_version(), (3, 1));
+        let restored = MetadataResponse::from_bytes(&response.to_bytes()).unwrap();
+        assert_eq!(restored, response);
+
+        // Version 3.0 serialization, without `signer_verifying_key`
+        let mut bytes = b"MdRs".to_vec();
+        bytes.extend(3u16.to_be_bytes());
+        bytes.extend(0u16.to_be_bytes());
+        bytes.extend(messagepack_serialize(&(&response.signature, &payload)).iter());
+
+        let restored = MetadataResponse::from_bytes(&bytes).unwrap();
+        assert_eq!(restored.signer_verifying_key(), None);
+        assert_eq!(restored.verify(&signer.verifying_key()).unwrap(), payload);
+    }
+
+    #[test]
+    fn cosignatures() {
+        let signer = Signer::new(SecretKey::random());
+        let watcher = Signer::new(SecretKey::random());
+        let outsider = Signer::new(SecretKey::random());
+        let nodes = [make_node_metadata(&signer, 123)];
+        let payload = MetadataResponsePayload::new(123, &nodes);
+
+        let response = MetadataResponse::new(&signer, &payload);
+        assert!(response.cosigning_keys().is_empty());
+        assert_eq!(response.wire_version(), (3, 0));
+        assert!(response.verify_all(&[signer.verifying_key()]));
+        assert!(!response.verify_all(&[signer.verifying_key(), watcher.verifying_key()]));
+
+        let response = response.cosign(&watcher);
+        assert_eq!(response.cosigning_keys(), [watcher.verifying_key()]);
+        assert!(response.verify_all(&[signer.verifying_key(), watcher.verifying_key()]));
+        assert!(!response.verify_all(&[outsider.verifying_key()]));
+
+        assert_eq!(response.wire_version(), (3, 2));
+        let restored = MetadataResponse::from_bytes(&response.to_bytes()).unwrap();
+        assert!(restored.verify_all(&[watcher.verifying_key(), signer.verifying_key()]));
+        assert_eq!(restored, response);
+
+        // Version 3.1 serialization, without `cosignatures`
+        let mut bytes = b"MdRs".to_vec();
+        bytes.extend(3u16.to_be_bytes());
+        bytes.extend(1u16.to_be_bytes());
+        bytes.extend(
+            messagepack_serialize(&(
+                &response.signature,
+                &payload,
+                &response.signer_verifying_key,
+            ))
+            .iter(),
+        );
+        let restored = MetadataResponse::from_bytes(&bytes).unwrap();
+        assert!(restored.cosigning_keys().is_empty());
+        assert!(restored.verify_all(&[signer.verifying_key()]));
+    }
+
+    #[test]
+    fn verified_response_keeps_signature() {
+        let signer = Signer::new(SecretKey::random());
+        let nodes = [make_node_metadata(&signer, 123)];
+        let response = MetadataResponse::new(&signer, &MetadataResponsePayload::new(123, &nodes));
+        let bytes = response.to_bytes();
+
+        let verified = response.into_verified(&signer.verifying_key()).unwrap();
+        assert_eq!(verified.response().to_bytes(), bytes);
+
+        let relayed = verified.into_response();
+        assert_eq!(relayed.to_bytes(), bytes);
+        assert!(relayed.into_verified(&signer.verifying_key()).is_ok());
+    }
+
+    #[test]
+    fn announce_nodes_limit() {
+        let signer = Signer::new(SecretKey::random());
+        let nodes = [
+            make_node_metadata(&signer, 123),
+            make_node_metadata(&signer, 124),
+        ];
+        let checksum = FleetStateChecksum::from_nodes(&nodes, None);
+
+        let request_bytes = MetadataRequest::new(&checksum, &nodes).to_bytes();
+        assert!(MetadataRequest::from_bytes_with_limit(&request_bytes, 2).is_ok());
+        assert!(matches!(
+            MetadataRequest::from_bytes_with_limit(&request_bytes, 1),
+            Err(DeserializationError::TooLarge {
+                limit: 1,
+                received: 2
+            })
+        ));
+
+        let payload = MetadataResponsePayload::new(123, &nodes);
+        let response = MetadataResponse::new(&signer, &payload);
+        let response_bytes = response.to_bytes();
+        assert!(MetadataResponse::from_bytes_with_limit(&response_bytes, 2).is_ok());
+        assert!(matches!(
+            MetadataResponse::from_bytes_with_limit(&response_bytes, 1),
+            Err(DeserializationError::TooLarge {
+                limit: 1,
+                received: 2
+            })
+        ));
+
+        // A response claiming more nodes than allowed is rejected
+        // before any of them are deserialized.
+        let mut bytes = response_bytes[..8].to_vec();
+        bytes.push(0x93); // array of 3 elements
+        bytes.extend(messagepack_serialize(&response.signature).iter());
+        bytes.push(0x92); // array of 2 elements
+        bytes.push(123); // timestamp
+        bytes.push(0xdd); // array32
+        bytes.extend(((MetadataResponse::MAX_ANNOUNCE_NODES + 1) as u32).to_be_bytes());
+        let error = MetadataResponse::from_bytes(&bytes).unwrap_err();
+        assert!(format!("{}", error).contains("payload too large"));
+    }
+
+    #[test]
+    fn certificate_size_limit() {
+        let signer = Signer::new(SecretKey::random());
+        let mut payload = make_node_metadata(&signer, 123).payload;
+
+        payload.certificate_der =
+            vec![3; NodeMetadataPayload::MAX_CERTIFICATE_SIZE].into_boxed_slice();
+        let node = NodeMetadata::new(&signer, &payload);
+        assert!(NodeMetadata::from_bytes(&node.to_bytes()).is_ok());
+
+        payload.certificate_der =
+            vec![3; NodeMetadataPayload::MAX_CERTIFICATE_SIZE + 1].into_boxed_slice();
+        let node = NodeMetadata::new(&signer, &payload);
+        let error = NodeMetadata::from_bytes(&node.to_bytes()).unwrap_err();
+        assert!(format!("{}", error).contains("payload too large"));
+    }
+
+    #[test]
+    fn merge_all() {
+        let ursula_signer = Signer::new(SecretKey::random());
+        let response = |nodes: &[NodeMetadata]| {
+            MetadataResponse::new(&ursula_signer, &MetadataResponsePayload::new(123, nodes))
+                .verify_detailed(&ursula_signer.verifying_key())
+                .unwrap()
+        };
+
+        let (node1_old, node1_new) = (make_node(1, 100), make_node(1, 200));
+        let (node2, node3) = (make_node(2, 100), make_node(3, 100));
+        let responses = [
+            response(&[node3.clone(), node1_old.clone()]),
+            response(&[node1_new.clone(), node2.clone()]),
+            response(&[node1_old]),
+        ];
+
+        assert_eq!(
+            VerifiedMetadataResponse::merge_all(&responses),
+            [node1_new, node2, node3]
+        );
+        assert!(VerifiedMetadataResponse::merge_all(&[]).is_empty());
+        assert!(!responses[0].is_empty());
+        assert!(response(&[]).is_empty());
+    }
+
+    #[test]
+    fn most_recent() {
+        let signer = Signer::new(SecretKey::random());
+        let old = make_node_metadata(&signer, 100);
+        let new1 = make_node_metadata(&signer, 200);
+        let new2 = make_node_metadata(&signer, 200);
+
+        assert!(NodeMetadata::most_recent(&[]).is_none());
+        assert_eq!(NodeMetadata::most_recent([&old]), Some(&old));
+
+        let result = NodeMetadata::most_recent([&old, &new1, &new2]).unwrap();
+        assert_eq!(result.payload.timestamp_epoch, 200);
+        // The result does not depend on the order of the candidates
+        assert_eq!(
+            NodeMetadata::most_recent([&new2, &old, &new1]),
+            Some(result)
+        );
+    }
+
+    #[test]
+    fn verify_detailed() {
+        let signer = Signer::new(SecretKey::random());
+        let another_signer = Signer::new(SecretKey::random());
+
+        let node = make_node_metadata(&signer, 123);
+        assert!(node.clone().verify_detailed().is_ok());
+        let mut forged_node = node.clone();
+        forged_node.payload.port += 1;
+        assert_eq!(
+            forged_node.verify_detailed().unwrap_err(),
+            VerificationFailure::InvalidSignature
+        );
+
+        let payload = MetadataResponsePayload::new(123, &[node]);
+        let response = MetadataResponse::new(&signer, &payload);
+        // Without the signer's key attached, the failure cannot be diagnosed
+        assert_eq!(
+            response
+                .clone()
+                .verify_detailed(&another_signer.verifying_key())
+                .unwrap_err(),
+            VerificationFailure::InvalidSignature
+        );
+
+        let response = response.with_signer_verifying_key(&signer.verifying_key());
+        assert!(response
+            .clone()
+            .verify_detailed(&signer.verifying_key())
+            .is_ok());
+        assert_eq!(
+            response
+                .clone()
+                .verify_detailed(&another_signer.verifying_key())
+                .unwrap_err(),
+            VerificationFailure::UnexpectedSigner
+        );
+
+        let mut forged_response = response;
+        forged_response.payload.timestamp_epoch += 1;
+        assert_eq!(
+            forged_response
+                .verify_detailed(&signer.verifying_key())
+                .unwrap_err(),
+            VerificationFailure::InvalidSignature
+        );
+    }
+
+    #[test]
+    fn invalid_domain() {
+        let signer = Signer::new(SecretKey::random());
+        let mut payload = make_node_metadata(&signer, 123).payload;
+        payload.domain = messagepack_deserialize(&messagepack_serialize(&"Lynx")).unwrap();
+        let node = NodeMetadata::new(&signer, &payload);
+
+        // Still readable and correctly signed, but not accepted
+        let restored = NodeMetadata::from_bytes(&node.to_bytes()).unwrap();
+        assert_eq!(restored.payload.domain.as_ref(), "Lynx");
+        assert!(restored.verify());
+        assert!(restored.clone().into_verified().is_err());
+        assert_eq!(
+            restored.verify_detailed().unwrap_err(),
+            VerificationFailure::InvalidDomain
+        );
+    }
+
+    #[test]
+    fn canonical_signing_bytes() {
+        // Build the expected encoding field by field, independently of the `serde` derive,
+        // so that a change in the field order or encoding is caught.
+        let payload = make_node(1, 123).payload;
+        let mut expected = Vec::new();
+        rmp::encode::write_array_len(&mut expected, 10).unwrap();
+        rmp::encode::write_bin(&mut expected, payload.staking_provider_address.as_ref()).unwrap();
+        rmp::encode::write_str(&mut expected, payload.domain.as_ref()).unwrap();
+        rmp::encode::write_uint(&mut expected, payload.timestamp_epoch.into()).unwrap();
+        expected.extend(messagepack_serialize(&payload.verifying_key).iter());
+        expected.extend(messagepack_serialize(&payload.encrypting_key).iter());
+        expected.extend(messagepack_serialize(&payload.ferveo_public_key).iter());
+        rmp::encode::write_bin(&mut expected, &payload.certificate_der).unwrap();
+        rmp::encode::write_str(&mut expected, &payload.host).unwrap();
+        rmp::encode::write_uint(&mut expected, payload.port.into()).unwrap();
+        expected.extend(messagepack_serialize(&payload.operator_signature).iter());
+
+        assert_eq!(payload.signing_bytes().as_ref(), expected.as_slice());
+    }
+
+    #[test]
+    fn verify_full() {
+        let signer = Signer::new(SecretKey::random());
+        let mut nodes = [
+            make_node_metadata(&signer, 123),
+            make_node_metadata(&signer, 124),
+            make_node_metadata(&signer, 125),
+        ];
+
+        let response = MetadataResponse::new(&signer, &MetadataResponsePayload::new(123, &nodes));
+        assert!(response.verify_full(&signer.verifying_key()).is_ok());
+
+        nodes[1].payload.port += 1;
+        nodes[2].payload.port += 1;
+        let response = MetadataResponse::new(&signer, &MetadataResponsePayload::new(123, &nodes))
+            .with_signer_verifying_key(&signer.verifying_key());
+        assert_eq!(
+            response
+                .clone()
+                .verify_full(&signer.verifying_key())
+                .unwrap_err(),
+            VerificationFailure::InvalidNode(1)
+        );
+        // The outer signature is checked first
+        assert_eq!(
+            response
+                .verify_full(&Signer::new(SecretKey::random()).verifying_key())
+                .unwrap_err(),
+            VerificationFailure::UnexpectedSigner
+        );
+    }
+
+    #[test]
+    fn announcement_stats() {
+        let signer = Signer::new(SecretKey::random());
+        let mut nodes = [
+            make_node_metadata(&signer, 123),
+            make_node_metadata(&signer, 124),
+            make_node_metadata(&signer, 125),
+        ];
+        let mut payload = nodes[1].payload.clone();
+        payload.domain = Domain::new("mainnet").unwrap();
+        nodes[1] = NodeMetadata::new(&signer, &payload);
+        nodes[2].payload.port += 1;
+
+        let checksum = FleetStateChecksum::from_nodes(&nodes, None);
+        let request = MetadataRequest::new(&checksum, &nodes);
+        assert_eq!(
+            request.announcement_stats("lynx"),
+            AnnouncementStats {
+                total: 3,
+                verified: 2,
+                on_domain: 1,
+            }
+        );
+        // The expected domain is normalized
+        assert_eq!(request.announcement_stats(" Mainnet").on_domain, 1);
+        assert_eq!(request.announcement_stats("main net").on_domain, 0);
+    }
+}
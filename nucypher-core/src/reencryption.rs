@@ -152,20 +152,20 @@ impl ReencryptionResponse {
             .into_iter()
             .zip(capsules.iter())
             .map(|(cfrag, capsule)| {
-                cfrag.verify(
-                    capsule,
-                    alice_verifying_key,
-                    policy_encrypting_key,
-                    bob_encrypting_key,
-                )
+                // `verify()` returns a (CapsuleFragVerificationError, CapsuleFrag) pair
+                // in the error case, but at this point nobody's interested in that.
+                cfrag
+                    .verify(
+                        capsule,
+                        alice_verifying_key,
+                        policy_encrypting_key,
+                        bob_encrypting_key,
+                    )
+                    .map_err(|_err| VerificationError)
             })
             .collect::<Result<Vec<_>, _>>();
 
-        // From the above statement we get a list of (CapsuleFragVerificationError, CapsuleFrag)
-        // in the error case, but at this point nobody's interested in that.
-        vcfrags
-            .map(|vcfrags| vcfrags.into_boxed_slice())
-            .map_err(|_err| VerificationError)
+        vcfrags.map(|vcfrags| vcfrags.into_boxed_slice())
     }
 }
 
@@ -195,12 +195,15 @@ impl<'a> ProtocolObject<'a> for ReencryptionResponse {}
 
 #[cfg(test)]
 mod tests {
+    use alloc::vec::Vec;
+
     use umbral_pre::SecretKey;
-    use umbral_pre::{encrypt, generate_kfrags, Signer};
+    use umbral_pre::{encrypt, generate_kfrags, reencrypt, Signer};
 
+    use crate::versioning::ProtocolObject;
     use crate::{Conditions, Context, EncryptedKeyFrag, HRAC};
 
-    use super::ReencryptionRequest;
+    use super::{ReencryptionRequest, ReencryptionResponse};
 
     #[test]
     fn conditions_and_context_are_different() {
@@ -244,4 +247,63 @@ mod tests {
         let context = request.context.unwrap();
         assert_eq!(context.as_ref(), "efgh");
     }
+
+    #[test]
+    fn reencryption_response() {
+        let delegating_sk = SecretKey::random();
+        let policy_encrypting_key = delegating_sk.public_key();
+        let alice_signer = Signer::new(SecretKey::random());
+        let bob_pk = SecretKey::random().public_key();
+        let ursula_signer = Signer::new(SecretKey::random());
+
+        let capsules = (0..3)
+            .map(|_| encrypt(&policy_encrypting_key, b"message").unwrap().0)
+            .collect::<Vec<_>>();
+        let kfrags = generate_kfrags(&delegating_sk, &bob_pk, &alice_signer, 2, 3, true, true);
+        let vcfrags = capsules
+            .iter()
+            .map(|capsule| reencrypt(capsule, kfrags[0].clone()))
+            .collect::<Vec<_>>();
+
+        let response =
+            ReencryptionResponse::new(&ursula_signer, capsules.iter().zip(vcfrags.iter().cloned()));
+
+        // mimic serialization/deserialization over the wire
+        let response = ReencryptionResponse::from_bytes(&response.to_bytes()).unwrap();
+
+        let verified = response
+            .clone()
+            .verify(
+                &capsules,
+                &alice_signer.verifying_key(),
+                &ursula_signer.verifying_key(),
+                &policy_encrypting_key,
+                &bob_pk,
+            )
+            .unwrap();
+        assert_eq!(verified.as_ref(), vcfrags.as_slice());
+
+        // Wrong Ursula key
+        assert!(response
+            .clone()
+            .verify(
+                &capsules,
+                &alice_signer.verifying_key(),
+                &alice_signer.verifying_key(),
+                &policy_encrypting_key,
+                &bob_pk,
+            )
+            .is_err());
+
+        // Mismatched number of capsules
+        assert!(response
+            .verify(
+                &capsules[..2],
+                &alice_signer.verifying_key(),
+                &ursula_signer.verifying_key(),
+                &policy_encrypting_key,
+                &bob_pk,
+            )
+            .is_err());
+    }
 }
@@ -1,15 +1,23 @@
 use alloc::boxed::Box;
 use alloc::collections::BTreeSet;
+use alloc::format;
 use alloc::string::String;
+use alloc::string::ToString;
+use alloc::vec::Vec;
+use core::fmt;
 
+use rmp::decode;
 use serde::{Deserialize, Serialize};
+use sha2::{digest::Update, Digest, Sha256};
 use umbral_pre::Capsule;
 
 use crate::address::Address;
 use crate::conditions::Conditions;
+use crate::hrac::HRAC;
 use crate::message_kit::MessageKit;
 use crate::versioning::{
-    messagepack_deserialize, messagepack_serialize, ProtocolObject, ProtocolObjectInner,
+    messagepack_deserialize, messagepack_serialize, split_header, DeserializationError,
+    ProtocolObject, ProtocolObjectInner,
 };
 
 /// An object encapsulating the information necessary for retrieval of cfrags from Ursulas.
@@ -23,18 +31,49 @@ pub struct RetrievalKit {
     pub queried_addresses: BTreeSet<Address>,
     /// A blob of bytes containing decryption conditions for this message.
     pub conditions: Option<Conditions>,
+    // Added in the minor version 1.
+    #[serde(default)]
+    failed_addresses: BTreeSet<Address>,
+    // Added in the minor version 2.
+    #[serde(default)]
+    hrac: Option<HRAC>,
 }
 
 impl RetrievalKit {
     /// Creates a new retrieval kit from a message kit.
+    ///
+    /// A message kit does not reference the policy it was encrypted for,
+    /// so the kit is not bound to any HRAC; use [`with_hrac`](Self::with_hrac) for that.
     pub fn from_message_kit(message_kit: &MessageKit) -> Self {
         Self {
-            capsule: message_kit.capsule.clone(),
+            capsule: message_kit.capsule().clone(),
             queried_addresses: BTreeSet::<Address>::new(),
             conditions: message_kit.conditions.clone(),
+            failed_addresses: BTreeSet::new(),
+            hrac: None,
         }
     }
 
+    /// Creates retrieval kits for all the capsules of a message kit,
+    /// with no addresses queried yet.
+    ///
+    /// A [`MessageKit`] currently holds a single capsule, so the result always has one element,
+    /// same as [`from_message_kit`](Self::from_message_kit).
+    pub fn all_from_message_kit(message_kit: &MessageKit) -> Vec<Self> {
+        [message_kit.capsule()]
+            .into_iter()
+            .map(|capsule| Self::new(capsule, [], message_kit.conditions.as_ref()))
+            .collect()
+    }
+
+    /// Returns `true` if the retrieval kit was created for the given message kit
+    /// (that is, they have the same capsule and conditions).
+    ///
+    /// The kit already contains the capsule, so no separate fingerprint is stored.
+    pub fn matches(&self, message_kit: &MessageKit) -> bool {
+        &self.capsule == message_kit.capsule() && self.conditions == message_kit.conditions
+    }
+
     /// Creates a new retrieval kit recording the addresses already queried for reencryption.
     pub fn new(
         capsule: &Capsule,
@@ -46,17 +85,323 @@ impl RetrievalKit {
             capsule: capsule.clone(),
             queried_addresses: queried_addresses.into_iter().collect(),
             conditions: conditions.cloned(),
+            failed_addresses: BTreeSet::new(),
+            hrac: None,
+        }
+    }
+
+    /// Records the addresses of the Ursulas that were queried for reencryption,
+    /// but failed to respond with valid cfrags (e.g. timed out),
+    /// so that the retrieval can be resumed avoiding them.
+    ///
+    /// The addresses present in `queried_addresses` are ignored,
+    /// since the cfrags from them have already been received.
+    pub fn with_failed_addresses(
+        mut self,
+        failed_addresses: impl IntoIterator<Item = Address>,
+    ) -> Self {
+        let queried_addresses = &self.queried_addresses;
+        self.failed_addresses.extend(
+            failed_addresses
+                .into_iter()
+                .filter(|address| !queried_addresses.contains(address)),
+        );
+        self
+    }
+
+    /// Creates a view of a serialized retrieval kit without deserializing it,
+    /// giving access to the queried addresses without allocations
+    /// (e.g. for routing many kits).
+    ///
+    /// Only the header, the queried addresses (including their ascending order),
+    /// and the structure of the capsule are validated, so a successful result does not guarantee
+    /// that [`from_bytes`](ProtocolObject::from_bytes) would succeed.
+    pub fn borrow_from_bytes(bytes: &[u8]) -> Result<RetrievalKitRef<'_>, DeserializationError> {
+        let (_minor_version, payload) = split_header::<Self>(bytes)?;
+        let malformed = |error_msg: &str| DeserializationError::BadPayload {
+            error_msg: error_msg.into(),
+        };
+
+        // The layout (same in all the supported versions) is
+        // `[capsule, [address, ...], conditions, ...]`.
+        let mut reader = payload;
+        if decode::read_array_len(&mut reader).map_err(|_| malformed("expected an array"))? < 3 {
+            return Err(malformed("too few fields"));
+        }
+
+        // The capsule is serialized as an array of byte strings (its points and scalar).
+        let capsule_start = reader;
+        let capsule_fields =
+            decode::read_array_len(&mut reader).map_err(|_| malformed("expected the capsule"))?;
+        for _ in 0..capsule_fields {
+            let length = decode::read_bin_len(&mut reader)
+                .map_err(|_| malformed("expected the capsule bytes"))?
+                as usize;
+            reader = reader
+                .get(length..)
+                .ok_or_else(|| malformed("truncated capsule"))?;
+        }
+        let capsule = &capsule_start[..capsule_start.len() - reader.len()];
+
+        let count = decode::read_array_len(&mut reader)
+            .map_err(|_| malformed("expected an array of addresses"))? as usize;
+        let addresses_start = reader;
+        let mut previous_address: Option<&[u8]> = None;
+        for _ in 0..count {
+            let length =
+                decode::read_bin_len(&mut reader).map_err(|_| malformed("expected an address"))?;
+            if length as usize != Address::SIZE || reader.len() < Address::SIZE {
+                return Err(malformed("invalid address"));
+            }
+            let (address, rest) = reader.split_at(Address::SIZE);
+            // Addresses are compared byte-wise, same as `Address` values.
+            if matches!(previous_address, Some(previous) if previous >= address) {
+                return Err(malformed("the addresses are not in ascending order"));
+            }
+            previous_address = Some(address);
+            reader = rest;
+        }
+        let queried_addresses = &addresses_start[..addresses_start.len() - reader.len()];
+
+        Ok(RetrievalKitRef {
+            capsule,
+            queried_addresses,
+            queried_addresses_count: count,
+        })
+    }
+
+    /// Returns the addresses of the Ursulas that failed to respond with valid cfrags
+    /// (see [`with_failed_addresses`](Self::with_failed_addresses)).
+    pub fn failed_addresses(&self) -> &BTreeSet<Address> {
+        &self.failed_addresses
+    }
+
+    /// Binds the retrieval kit to the policy with the given HRAC,
+    /// so that it can be checked with [`matches_policy`](Self::matches_policy)
+    /// before querying the policy's Ursulas.
+    pub fn with_hrac(mut self, hrac: &HRAC) -> Self {
+        self.hrac = Some(*hrac);
+        self
+    }
+
+    /// Returns the HRAC of the policy the kit is bound to, if any.
+    pub fn hrac(&self) -> Option<&HRAC> {
+        self.hrac.as_ref()
+    }
+
+    /// Returns `false` if the kit is bound to a policy other than the one with the given HRAC.
+    ///
+    /// Kits not bound to any policy (including the ones predating the HRAC field)
+    /// match any policy.
+    pub fn matches_policy(&self, hrac: &HRAC) -> bool {
+        match &self.hrac {
+            Some(own_hrac) => own_hrac == hrac,
+            None => true,
         }
     }
 }
 
+/// A view of a serialized [`RetrievalKit`] borrowing from the input bytes
+/// (see [`RetrievalKit::borrow_from_bytes`]).
+#[derive(Debug, Clone, Copy)]
+pub struct RetrievalKitRef<'a> {
+    capsule: &'a [u8],
+    queried_addresses: &'a [u8],
+    queried_addresses_count: usize,
+}
+
+impl<'a> RetrievalKitRef<'a> {
+    /// Deserializes and returns the capsule.
+    ///
+    /// Only the structure of the capsule is checked on creation,
+    /// so this can still fail.
+    pub fn capsule(&self) -> Result<Capsule, DeserializationError> {
+        messagepack_deserialize(self.capsule)
+            .map_err(|error_msg| DeserializationError::BadPayload { error_msg })
+    }
+
+    /// Returns the number of the queried addresses.
+    pub fn queried_addresses_count(&self) -> usize {
+        self.queried_addresses_count
+    }
+
+    /// Returns an iterator over the queried addresses, in ascending order.
+    pub fn queried_addresses(&self) -> impl Iterator<Item = Address> + 'a {
+        let mut reader = self.queried_addresses;
+        // The addresses (and their order) were validated on creation,
+        // so the reads cannot fail.
+        core::iter::from_fn(move || {
+            decode::read_bin_len(&mut reader).ok()?;
+            let (address, rest) = reader.split_at(Address::SIZE);
+            reader = rest;
+            Address::try_from_bytes(address).ok()
+        })
+    }
+}
+
+fn check_disjoint(kit: &RetrievalKit) -> Result<(), String> {
+    match kit
+        .failed_addresses
+        .intersection(&kit.queried_addresses)
+        .next()
+    {
+        Some(address) => Err(format!(
+            "{:?} is both in queried and failed addresses",
+            address
+        )),
+        None => Ok(()),
+    }
+}
+
 impl<'a> ProtocolObjectInner<'a> for RetrievalKit {
     fn brand() -> [u8; 4] {
         *b"RKit"
     }
 
     fn version() -> (u16, u16) {
-        (3, 0)
+        (3, 2)
+    }
+
+    fn unversioned_to_bytes(&self) -> Box<[u8]> {
+        // The fields not needed for the minor version are omitted,
+        // which gives the same representation as the struct serialized by that version.
+        match self.minor_version() {
+            0 => messagepack_serialize(&(&self.capsule, &self.queried_addresses, &self.conditions)),
+            1 => messagepack_serialize(&(
+                &self.capsule,
+                &self.queried_addresses,
+                &self.conditions,
+                &self.failed_addresses,
+            )),
+            _ => messagepack_serialize(&self),
+        }
+    }
+
+    fn minor_version(&self) -> u16 {
+        // `failed_addresses` was added in the minor version 1, and `hrac` in the minor version 2.
+        if self.hrac.is_some() {
+            2
+        } else if !self.failed_addresses.is_empty() {
+            1
+        } else {
+            0
+        }
+    }
+
+    fn supported_minor_versions() -> &'static [u16] {
+        &[0, 1, 2]
+    }
+
+    fn validate(&self) -> Result<(), String> {
+        check_disjoint(self)
+    }
+
+    fn unversioned_from_bytes(minor_version: u16, bytes: &[u8]) -> Option<Result<Self, String>> {
+        // Version 3.0 does not have `failed_addresses`, and versions 3.0 and 3.1
+        // do not have `hrac`; the missing fields will be set to the defaults.
+        if minor_version <= 2 {
+            Some(messagepack_deserialize(bytes))
+        } else {
+            None
+        }
+    }
+}
+
+impl<'a> ProtocolObject<'a> for RetrievalKit {}
+
+/// Errors that can happen when creating a [`BatchRetrievalKit`].
+#[derive(Debug, PartialEq, Eq)]
+pub enum BatchRetrievalKitError {
+    /// The capsule at the given position is a repetition of a previous one.
+    DuplicateCapsule(usize),
+}
+
+impl fmt::Display for BatchRetrievalKitError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::DuplicateCapsule(index) => write!(f, "duplicate capsule at position {}", index),
+        }
+    }
+}
+
+/// A retrieval kit for a message encrypted over several capsules.
+/// Contains the capsules and the checksum addresses of Ursulas from which the requester
+/// already received cfrags for all of them.
+#[derive(PartialEq, Debug, Serialize, Deserialize)]
+pub struct BatchRetrievalKit {
+    /// The capsules of the message parts.
+    pub capsules: Vec<Capsule>,
+    /// The addresses that have already been queried for reencryption.
+    pub queried_addresses: BTreeSet<Address>,
+    /// A blob of bytes containing decryption conditions for this message.
+    pub conditions: Option<Conditions>,
+}
+
+impl BatchRetrievalKit {
+    /// Creates a new batch retrieval kit recording the addresses already queried
+    /// for reencryption.
+    ///
+    /// Panics if `capsules` contains duplicates;
+    /// see [`try_new`](Self::try_new) for a fallible version.
+    pub fn new(
+        capsules: &[Capsule],
+        queried_addresses: impl IntoIterator<Item = Address>,
+        conditions: Option<&Conditions>,
+    ) -> Self {
+        match Self::try_new(capsules, queried_addresses, conditions) {
+            Ok(kit) => kit,
+            // Panic here since violation of this condition indicates a bug on the caller's side.
+            Err(err) => panic!("{}", err),
+        }
+    }
+
+    /// Creates a new batch retrieval kit recording the addresses already queried
+    /// for reencryption, returning an error if `capsules` contains duplicates.
+    pub fn try_new(
+        capsules: &[Capsule],
+        queried_addresses: impl IntoIterator<Item = Address>,
+        conditions: Option<&Conditions>,
+    ) -> Result<Self, BatchRetrievalKitError> {
+        check_unique(capsules)?;
+        Ok(Self {
+            capsules: capsules.to_vec(),
+            queried_addresses: queried_addresses.into_iter().collect(),
+            conditions: conditions.cloned(),
+        })
+    }
+}
+
+/// Returns a fingerprint of the capsule: the SHA-256 digest of its canonical
+/// byte representation (see [`Capsule::to_bytes_simple`]).
+///
+/// Two capsules have the same fingerprint if and only if they are equal
+/// (barring hash collisions), and the fingerprint does not change
+/// when the capsule is serialized and deserialized,
+/// so it can be used to index capsules (which are not `Ord` or `Hash`).
+pub fn capsule_fingerprint(capsule: &Capsule) -> [u8; 32] {
+    Sha256::new()
+        .chain(capsule.to_bytes_simple())
+        .finalize()
+        .into()
+}
+
+fn check_unique(capsules: &[Capsule]) -> Result<(), BatchRetrievalKitError> {
+    let mut seen = BTreeSet::new();
+    for (index, capsule) in capsules.iter().enumerate() {
+        if !seen.insert(capsule_fingerprint(capsule)) {
+            return Err(BatchRetrievalKitError::DuplicateCapsule(index));
+        }
+    }
+    Ok(())
+}
+
+impl<'a> ProtocolObjectInner<'a> for BatchRetrievalKit {
+    fn brand() -> [u8; 4] {
+        *b"BRKt"
+    }
+
+    fn version() -> (u16, u16) {
+        (1, 0)
     }
 
     fn unversioned_to_bytes(&self) -> Box<[u8]> {
@@ -70,6 +415,195 @@ impl<'a> ProtocolObjectInner<'a> for RetrievalKit {
             None
         }
     }
+
+    fn validate(&self) -> Result<(), String> {
+        check_unique(&self.capsules).map_err(|err| err.to_string())
+    }
 }
 
-impl<'a> ProtocolObject<'a> for RetrievalKit {}
+impl<'a> ProtocolObject<'a> for BatchRetrievalKit {}
+
+#[cfg(test)]
+mod tests {
+    use umbral_pre::SecretKey;
+
+    use super::{capsule_fingerprint, BatchRetrievalKit, BatchRetrievalKitError, RetrievalKit};
+    use crate::address::Address;
+    use crate::conditions::Conditions;
+    use crate::hrac::HRAC;
+    use crate::message_kit::MessageKit;
+    use crate::versioning::{messagepack_serialize, ProtocolObject};
+
+    #[test]
+    fn matches() {
+        let pk = SecretKey::random().public_key();
+        let conditions = Conditions::new("abcd");
+        let message_kit = MessageKit::new(&pk, b"message", Some(&conditions));
+        let retrieval_kit = RetrievalKit::from_message_kit(&message_kit);
+        assert!(retrieval_kit.matches(&message_kit));
+
+        let another_kit = MessageKit::new(&pk, b"message", Some(&conditions));
+        assert!(!retrieval_kit.matches(&another_kit));
+
+        let same_capsule = RetrievalKit::new(message_kit.capsule(), [], None);
+        assert!(!same_capsule.matches(&message_kit));
+    }
+
+    #[test]
+    fn duplicate_capsules() {
+        let pk = SecretKey::random().public_key();
+        let capsule1 = MessageKit::new(&pk, b"message1", None).capsule().clone();
+        let capsule2 = MessageKit::new(&pk, b"message2", None).capsule().clone();
+
+        assert!(
+            BatchRetrievalKit::try_new(&[capsule1.clone(), capsule2.clone()], [], None).is_ok()
+        );
+        assert_eq!(
+            BatchRetrievalKit::try_new(
+                &[capsule1.clone(), capsule2.clone(), capsule1.clone()],
+                [],
+                None
+            ),
+            Err(BatchRetrievalKitError::DuplicateCapsule(2))
+        );
+
+        let mut kit = BatchRetrievalKit::new(&[capsule1.clone(), capsule2], [], None);
+        kit.capsules[1] = capsule1;
+        assert!(BatchRetrievalKit::from_bytes(&kit.to_bytes()).is_err());
+        #[cfg(feature = "bincode")]
+        assert!(BatchRetrievalKit::from_bincode_bytes(&kit.to_bincode_bytes()).is_err());
+    }
+
+    #[test]
+    fn failed_addresses() {
+        let address = |i: u8| Address::new(&[i; Address::SIZE]);
+        let message_kit = MessageKit::new(&SecretKey::random().public_key(), b"message", None);
+
+        let kit = RetrievalKit::new(message_kit.capsule(), [address(1)], None)
+            .with_failed_addresses([address(1), address(2)]);
+        // Queried addresses are not recorded as failed
+        assert_eq!(kit.failed_addresses(), &[address(2)].into());
+
+        let restored = RetrievalKit::from_bytes(&kit.to_bytes()).unwrap();
+        assert_eq!(restored, kit);
+
+        assert_eq!(kit.wire_version(), (3, 1));
+
+        // Version 3.0 serialization, without `failed_addresses`
+        let mut bytes = b"RKit".to_vec();
+        bytes.extend(3u16.to_be_bytes());
+        bytes.extend(0u16.to_be_bytes());
+        bytes.extend(
+            messagepack_serialize(&(&kit.capsule, &kit.queried_addresses, &kit.conditions)).iter(),
+        );
+        let restored = RetrievalKit::from_bytes(&bytes).unwrap();
+        assert!(restored.failed_addresses().is_empty());
+        assert_eq!(restored.queried_addresses, kit.queried_addresses);
+        // Kits without failed addresses are still serialized as version 3.0,
+        // so that they can be read by the implementations not supporting the field.
+        assert_eq!(restored.to_bytes(), bytes.into_boxed_slice());
+
+        // An address cannot be both queried and failed
+        let mut kit = kit;
+        kit.queried_addresses.insert(address(2));
+        assert!(RetrievalKit::from_bytes(&kit.to_bytes()).is_err());
+    }
+
+    #[test]
+    fn hrac() {
+        let message_kit = MessageKit::new(&SecretKey::random().public_key(), b"message", None);
+        let hrac1 = HRAC::from([1; HRAC::SIZE]);
+        let hrac2 = HRAC::from([2; HRAC::SIZE]);
+
+        let kit = RetrievalKit::from_message_kit(&message_kit);
+        assert_eq!(kit.hrac(), None);
+        assert!(kit.matches_policy(&hrac1));
+        // Kits not bound to a policy are serialized with the lowest version they fit in
+        assert_eq!(kit.wire_version(), (3, 0));
+        let address = Address::new(&[1; Address::SIZE]);
+        let failed_kit =
+            RetrievalKit::from_message_kit(&message_kit).with_failed_addresses([address]);
+        assert_eq!(failed_kit.wire_version(), (3, 1));
+
+        let kit = kit.with_hrac(&hrac1);
+        assert!(kit.matches_policy(&hrac1));
+        assert!(!kit.matches_policy(&hrac2));
+        assert_eq!(kit.wire_version(), (3, 2));
+
+        let restored = RetrievalKit::from_bytes(&kit.to_bytes()).unwrap();
+        assert_eq!(restored.hrac(), Some(&hrac1));
+
+        // Version 3.1 serialization, without `hrac`
+        let mut bytes = b"RKit".to_vec();
+        bytes.extend(3u16.to_be_bytes());
+        bytes.extend(1u16.to_be_bytes());
+        bytes.extend(
+            messagepack_serialize(&(
+                &kit.capsule,
+                &kit.queried_addresses,
+                &kit.conditions,
+                &kit.failed_addresses,
+            ))
+            .iter(),
+        );
+        let restored = RetrievalKit::from_bytes(&bytes).unwrap();
+        assert_eq!(restored.hrac(), None);
+        assert!(restored.matches_policy(&hrac2));
+    }
+
+    #[test]
+    fn fingerprint() {
+        let pk = SecretKey::random().public_key();
+        let capsule = MessageKit::new(&pk, b"message", None).capsule().clone();
+        let another_capsule = MessageKit::new(&pk, b"message", None).capsule().clone();
+
+        let kit = RetrievalKit::new(&capsule, [], None);
+        let restored = RetrievalKit::from_bytes(&kit.to_bytes()).unwrap();
+        assert_eq!(
+            capsule_fingerprint(&restored.capsule),
+            capsule_fingerprint(&capsule)
+        );
+        assert_ne!(
+            capsule_fingerprint(&another_capsule),
+            capsule_fingerprint(&capsule)
+        );
+    }
+
+    #[test]
+    fn borrow_from_bytes() {
+        let address = |i: u8| Address::new(&[i; Address::SIZE]);
+        let message_kit = MessageKit::new(&SecretKey::random().public_key(), b"message", None);
+        let kit = RetrievalKit::new(message_kit.capsule(), [address(2), address(1)], None)
+            .with_failed_addresses([address(3)]);
+        let bytes = kit.to_bytes();
+
+        let kit_ref = RetrievalKit::borrow_from_bytes(&bytes).unwrap();
+        assert_eq!(kit_ref.capsule().unwrap(), kit.capsule);
+        assert_eq!(kit_ref.queried_addresses_count(), 2);
+        assert!(kit_ref
+            .queried_addresses()
+            .eq(kit.queried_addresses.iter().copied()));
+
+        for length in 0..bytes.len() {
+            if let Ok(kit_ref) = RetrievalKit::borrow_from_bytes(&bytes[..length]) {
+                // Only the trailing fields can be cut off
+                assert_eq!(kit_ref.queried_addresses_count(), 2);
+            }
+        }
+
+        // The addresses must be in ascending order
+        let position = |i: u8| {
+            bytes
+                .windows(Address::SIZE)
+                .position(|window| window == [i; Address::SIZE])
+                .unwrap()
+        };
+        let mut unordered = bytes.to_vec();
+        unordered[position(1)..position(1) + Address::SIZE].fill(2);
+        unordered[position(2)..position(2) + Address::SIZE].fill(1);
+        assert!(RetrievalKit::borrow_from_bytes(&unordered).is_err());
+        let mut duplicate = bytes.to_vec();
+        duplicate[position(1)..position(1) + Address::SIZE].fill(2);
+        assert!(RetrievalKit::borrow_from_bytes(&duplicate).is_err());
+    }
+}
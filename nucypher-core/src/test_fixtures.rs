@@ -0,0 +1,232 @@
+//! Deterministically generated protocol objects.
+//!
+//! All the random values (keys, signatures, encryption nonces) are derived from the given seed,
+//! so the same seed produces the same serialized objects on every platform.
+//! This makes them usable as test vectors for compatible implementations.
+
+use ferveo::api::Keypair as FerveoKeypair;
+use rand::{Rng, SeedableRng};
+use rand_chacha::ChaCha20Rng;
+use umbral_pre::{
+    encrypt_with_rng, generate_kfrags_with_rng, RecoverableSignature, SecretKey, Signer,
+};
+
+use crate::address::Address;
+use crate::conditions::Conditions;
+use crate::domain::Domain;
+use crate::hrac::HRAC;
+use crate::key_frag::EncryptedKeyFrag;
+use crate::message_kit::MessageKit;
+use crate::node_metadata::{NodeMetadata, NodeMetadataPayload};
+use crate::retrieval_kit::RetrievalKit;
+use crate::treasure_map::TreasureMap;
+
+/// Creates a secret key.
+pub fn secret_key(seed: u64) -> SecretKey {
+    SecretKey::random_with_rng(&mut ChaCha20Rng::seed_from_u64(seed))
+}
+
+/// Creates a secret key and a signer using it from a 32-byte seed.
+///
+/// Unlike [`secret_key`], the full seed is used, so the keys for different seeds
+/// are not limited to `2^64` possibilities.
+pub fn deterministic_keypair(seed: &[u8; 32]) -> (SecretKey, Signer) {
+    let secret_key = SecretKey::random_with_rng(&mut ChaCha20Rng::from_seed(*seed));
+    let signer = Signer::new(secret_key.clone());
+    (secret_key, signer)
+}
+
+/// Creates signed node metadata.
+///
+/// Note that the operator signature is a well-formed signature,
+/// but it is not made by an actual operator key.
+pub fn node_metadata(seed: u64) -> NodeMetadata {
+    let mut rng = ChaCha20Rng::seed_from_u64(seed);
+    let signer = Signer::new(SecretKey::random_with_rng(&mut rng));
+
+    let mut operator_signature = signer
+        .sign_with_rng(&mut rng, b"operator")
+        .to_be_bytes()
+        .to_vec();
+    operator_signature.push(0);
+
+    let ferveo_seed = rng.gen::<[u8; 32]>();
+    let ferveo_keypair =
+        FerveoKeypair::from_secure_randomness(&ferveo_seed).expect("the seed has the correct size");
+
+    let payload = NodeMetadataPayload {
+        staking_provider_address: Address::new(&rng.gen()),
+        domain: Domain::new("lynx").expect("the domain is valid"),
+        timestamp_epoch: 1_700_000_000,
+        verifying_key: signer.verifying_key(),
+        encrypting_key: SecretKey::random_with_rng(&mut rng).public_key(),
+        ferveo_public_key: ferveo_keypair.public_key(),
+        certificate_der: b"fixture certificate".to_vec().into_boxed_slice(),
+        host: "example.com".into(),
+        port: 9151,
+        operator_signature: RecoverableSignature::try_from_be_bytes(&operator_signature)
+            .expect("the signature is well-formed"),
+    };
+    NodeMetadata::new_with_rng(&mut rng, &signer, &payload)
+}
+
+/// Creates an encrypted message with conditions.
+pub fn message_kit(seed: u64) -> MessageKit {
+    let mut rng = ChaCha20Rng::seed_from_u64(seed);
+    let policy_encrypting_key = SecretKey::random_with_rng(&mut rng).public_key();
+    let (capsule, ciphertext) =
+        encrypt_with_rng(&mut rng, &policy_encrypting_key, b"fixture message")
+            .expect("the plaintext is small");
+    MessageKit::from_parts(
+        capsule,
+        ciphertext,
+        Some(&Conditions::new("{\"fixture\": true}")),
+    )
+}
+
+/// Creates a retrieval kit for [`message_kit`] with the same seed, with two queried addresses.
+pub fn retrieval_kit(seed: u64) -> RetrievalKit {
+    let message_kit = message_kit(seed);
+    RetrievalKit::new(
+        message_kit.capsule(),
+        [
+            Address::new(&[1; Address::SIZE]),
+            Address::new(&[2; Address::SIZE]),
+        ],
+        message_kit.conditions.as_ref(),
+    )
+}
+
+/// Creates a 2-of-3 treasure map.
+pub fn treasure_map(seed: u64) -> TreasureMap {
+    let mut rng = ChaCha20Rng::seed_from_u64(seed);
+    let delegating_sk = SecretKey::random_with_rng(&mut rng);
+    let publisher = Signer::new(SecretKey::random_with_rng(&mut rng));
+    let bob_pk = SecretKey::random_with_rng(&mut rng).public_key();
+    let hrac = HRAC::new(&publisher.verifying_key(), &bob_pk, b"fixture");
+
+    let kfrags = generate_kfrags_with_rng(
+        &mut rng,
+        &delegating_sk,
+        &bob_pk,
+        &publisher,
+        2,
+        3,
+        true,
+        true,
+    );
+    let destinations = kfrags
+        .into_vec()
+        .into_iter()
+        .enumerate()
+        .map(|(i, kfrag)| {
+            let ursula_pk = SecretKey::random_with_rng(&mut rng).public_key();
+            let ekfrag =
+                EncryptedKeyFrag::try_new_with_rng(&mut rng, &publisher, &ursula_pk, &hrac, kfrag)
+                    .expect("the key frag is small");
+            (Address::new(&[i as u8; Address::SIZE]), ekfrag)
+        })
+        .collect();
+
+    TreasureMap {
+        threshold: 2,
+        hrac,
+        destinations,
+        policy_encrypting_key: delegating_sk.public_key(),
+        publisher_verifying_key: publisher.verifying_key(),
+        expiration_epoch: None,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use sha3::{Digest, Keccak256};
+
+    use super::{
+        deterministic_keypair, message_kit, node_metadata, retrieval_kit, secret_key, treasure_map,
+    };
+    use crate::versioning::ProtocolObject;
+
+    // Golden vectors, to detect changes in the generated objects across versions and platforms.
+    const NODE_METADATA_1: &str = concat!(
+        "4e644d640004000092c440afce1c17c872e916762ce23ae765371cbc39ea599a38dcf30d927ade53fad7a15e",
+        "6c01f57bb8129002d3739481e4080290275ea6aac749a8e95fd5de657110e59ac414158a46ea72281f91ec05",
+        "d93b82c17e47e9106204a46c796e78ce6553f100c421039a21cfa668067afd9c29736673b2c30c6c19d51dae",
+        "085981f232a28745ede2fec42103773a9d30e5668ad98b735d9f8223f5b37520d0fb9093e66ffccf9c16f612",
+        "0dff91c4608d0f9a9130168153627fb687d82df2f93e59825bc2a08da6874b051b9514fbe3d9fcc705e14740",
+        "39297fc9029e2145070f7f0f64cc531fc0d3aa69104b777e82c4cf4f1d1cda6ef71bf94c2da700c7fb135e01",
+        "a7517b585c5c886f5da7fe01d2c41366697874757265206365727469666963617465ab6578616d706c652e63",
+        "6f6dcd23bfc44158bcf9a6d6e56c75e214ee5c2d3876fb17abd6a9908090c104379bc405592ab374a075667b",
+        "513019470029579080398d110d4ecbad2c45dcdd36d4aa4adc487a00",
+    );
+
+    const MESSAGE_KIT_1: &str = concat!(
+        "4d4b6974000300009393c4210367415a7dfaa7ed12c38523e486a8ee6cfc59cc6e44b3edd11646723bc08e0b",
+        "8cc42103b9c2a5488897e74681599118573a3b32b49b4383b327ff844ab9d3fe2dfd4b91c4205b2b574e805f",
+        "e394be0c061275d93f6999f2a8d758695633dd243f04b37b543fc43795fa4ae164486390451fd3e2b5ea4dcd",
+        "16b16667b9131a3ce21dace2b85df81f381c5607405031200bcc4c14168a8cd9a0e73bec290ac4b17b226669",
+        "7874757265223a20747275657d",
+    );
+
+    const RETRIEVAL_KIT_1: &str = concat!(
+        "524b6974000300009393c4210367415a7dfaa7ed12c38523e486a8ee6cfc59cc6e44b3edd11646723bc08e0b",
+        "8cc42103b9c2a5488897e74681599118573a3b32b49b4383b327ff844ab9d3fe2dfd4b91c4205b2b574e805f",
+        "e394be0c061275d93f6999f2a8d758695633dd243f04b37b543f92c414010101010101010101010101010101",
+        "0101010101c4140202020202020202020202020202020202020202b17b2266697874757265223a2074727565",
+        "7d",
+    );
+
+    // The serialized treasure map is large, so only its Keccak-256 digest is stored.
+    const TREASURE_MAP_1_DIGEST: &str =
+        "be3d1021a83862f65c44f675e45d3c7f869f551491d520465aa86ff700b22ca8";
+
+    const SECRET_KEY_1_PUBLIC_KEY: &str =
+        "039a21cfa668067afd9c29736673b2c30c6c19d51dae085981f232a28745ede2fe";
+
+    const DETERMINISTIC_KEYPAIR_1_VERIFYING_KEY: &str =
+        "02fa1f7356482c094a11420cba35bd3ff76d184b8002f06cfcc9e06867d359eee2";
+
+    #[test]
+    fn deterministic() {
+        assert_eq!(node_metadata(1).to_bytes(), node_metadata(1).to_bytes());
+        assert_ne!(node_metadata(1).to_bytes(), node_metadata(2).to_bytes());
+        assert!(node_metadata(1).verify());
+
+        assert_eq!(message_kit(1).to_bytes(), message_kit(1).to_bytes());
+        assert_eq!(retrieval_kit(1).to_bytes(), retrieval_kit(1).to_bytes());
+        assert!(retrieval_kit(1).matches(&message_kit(1)));
+        assert_eq!(treasure_map(1).to_bytes(), treasure_map(1).to_bytes());
+
+        let (sk1, signer1) = deterministic_keypair(&[1; 32]);
+        let (sk2, signer2) = deterministic_keypair(&[1; 32]);
+        let (sk3, _signer3) = deterministic_keypair(&[2; 32]);
+        assert_eq!(sk1.public_key(), sk2.public_key());
+        assert_ne!(sk1.public_key(), sk3.public_key());
+        assert_eq!(signer1.verifying_key(), signer2.verifying_key());
+        assert_eq!(signer1.verifying_key(), sk1.public_key());
+    }
+
+    #[test]
+    fn golden_vectors() {
+        assert_eq!(hex::encode(node_metadata(1).to_bytes()), NODE_METADATA_1);
+        assert_eq!(hex::encode(message_kit(1).to_bytes()), MESSAGE_KIT_1);
+        assert_eq!(hex::encode(retrieval_kit(1).to_bytes()), RETRIEVAL_KIT_1);
+        assert_eq!(
+            hex::encode(Keccak256::digest(treasure_map(1).to_bytes())),
+            TREASURE_MAP_1_DIGEST
+        );
+        assert_eq!(
+            hex::encode(secret_key(1).public_key().to_compressed_bytes()),
+            SECRET_KEY_1_PUBLIC_KEY
+        );
+        assert_eq!(
+            hex::encode(
+                deterministic_keypair(&[1; 32])
+                    .1
+                    .verifying_key()
+                    .to_compressed_bytes()
+            ),
+            DETERMINISTIC_KEYPAIR_1_VERIFYING_KEY
+        );
+    }
+}
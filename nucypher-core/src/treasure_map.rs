@@ -1,25 +1,112 @@
 use alloc::boxed::Box;
-use alloc::collections::BTreeMap;
+use alloc::collections::{BTreeMap, BTreeSet};
 use alloc::format;
 use alloc::string::String;
 use alloc::vec::Vec;
+use core::fmt;
 
-use serde::{Deserialize, Serialize};
+use rmp::decode;
+use serde::{Deserialize, Serialize, Serializer};
+use subtle::ConstantTimeEq;
 use umbral_pre::{
     decrypt_original, encrypt, serde_bytes, Capsule, EncryptionError, PublicKey, SecretKey,
     Signature, Signer, VerifiedKeyFrag,
 };
+use zeroize::Zeroizing;
 
 use crate::address::Address;
 use crate::hrac::HRAC;
-use crate::key_frag::{DecryptionError, EncryptedKeyFrag};
+use crate::key_frag::{AuthorizedDecryptionError, EncryptedKeyFrag};
+use crate::retrieval_kit::RetrievalKit;
 use crate::versioning::{
-    messagepack_deserialize, messagepack_serialize, ProtocolObject, ProtocolObjectInner,
+    messagepack_deserialize, messagepack_serialize, split_header, with_header, ProtocolObject,
+    ProtocolObjectInner,
 };
-use crate::RevocationOrder;
+use crate::{RevocationOrder, VerificationError};
+
+/// Errors that can happen during the creation or validation of a [`TreasureMap`].
+#[derive(Debug, PartialEq, Eq)]
+pub enum TreasureMapError {
+    /// Failed to encrypt a key frag for the given Ursula.
+    KeyFragEncryption(Address, EncryptionError),
+    /// The threshold is zero.
+    ZeroThreshold,
+    /// The given Ursula is not among the destinations of the treasure map.
+    UnknownDestination(Address),
+    /// The threshold is larger than the number of destinations.
+    ThresholdTooLarge {
+        /// The threshold of the treasure map.
+        threshold: u8,
+        /// The number of destinations in the treasure map.
+        shares: usize,
+    },
+}
+
+impl fmt::Display for TreasureMapError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::KeyFragEncryption(address, err) => write!(
+                f,
+                "failed to encrypt the key frag for {:?}: {}",
+                address, err
+            ),
+            Self::ZeroThreshold => write!(f, "threshold must be non-zero"),
+            Self::UnknownDestination(address) => {
+                write!(f, "{:?} is not a destination of the treasure map", address)
+            }
+            Self::ThresholdTooLarge { threshold, shares } => write!(
+                f,
+                "threshold ({}) cannot be larger than the total number of shares ({})",
+                threshold, shares
+            ),
+        }
+    }
+}
+
+/// The difference between the destinations of two treasure maps
+/// (see [`TreasureMap::diff`]).
+#[derive(Debug, PartialEq, Eq, Clone, Default)]
+pub struct TreasureMapDiff {
+    /// The addresses present only in the new map.
+    pub added: BTreeSet<Address>,
+    /// The addresses present only in the old map.
+    pub removed: BTreeSet<Address>,
+    /// The addresses present in both maps, with the same encrypted key frag.
+    pub unchanged: BTreeSet<Address>,
+    /// The addresses present in both maps, with different encrypted key frags.
+    pub changed: BTreeSet<Address>,
+}
+
+/// The next step of a retrieval (see [`TreasureMap::retrieval_plan`]).
+#[derive(Debug, PartialEq, Eq, Clone)]
+pub struct RetrievalPlan {
+    /// The addresses of the Ursulas that have not been queried yet, in ascending order,
+    /// except that the ones that previously failed to respond are placed at the end.
+    pub candidates: Vec<Address>,
+    /// The number of additional cfrags needed to reach the threshold.
+    pub remaining: usize,
+}
+
+impl RetrievalPlan {
+    /// Returns `true` if enough cfrags have already been obtained.
+    pub fn is_complete(&self) -> bool {
+        self.remaining == 0
+    }
+
+    /// Returns `true` if there are not enough candidates left to reach the threshold.
+    pub fn is_exhausted(&self) -> bool {
+        self.candidates.len() < self.remaining
+    }
+}
+
+// Prefixed to the message signed by `TreasureMap::sign()`, so that the signature
+// cannot be substituted for the one in `AuthorizedTreasureMap` (where the message
+// starts with a public key) or for a detached one (see `sign_detached()`).
+const TREASURE_MAP_SIGNATURE_TAG: &[u8] = b"TMapSig";
 
 /// A structure containing `KeyFrag` objects encrypted for Ursulas chosen for this policy.
-#[derive(PartialEq, Debug, Clone, Serialize, Deserialize)]
+#[derive(PartialEq, Clone, Serialize, Deserialize)]
+#[cfg_attr(not(feature = "redacted-debug"), derive(Debug))]
 pub struct TreasureMap {
     /// Threshold for successful re-encryption.
     pub threshold: u8,
@@ -28,9 +115,22 @@ pub struct TreasureMap {
     /// Encrypted key frags assigned to target Ursulas.
     pub destinations: BTreeMap<Address, EncryptedKeyFrag>,
     /// A key to create encrypted messages under this policy.
+    ///
+    /// A capsule does not commit to the key it was created with, so there is no way
+    /// to check that a message was encrypted with this key before the retrieval,
+    /// and the cfrags for a capsule created with a different key still pass verification.
+    /// The mismatch is only detected by
+    /// [`MessageKit::decrypt_reencrypted`](crate::MessageKit::decrypt_reencrypted),
+    /// which fails in that case.
     pub policy_encrypting_key: PublicKey,
     /// Publisher's verifying key.
     pub publisher_verifying_key: PublicKey,
+    /// The epoch (in seconds) after which the policy is expired.
+    ///
+    /// Not set by [`new`](Self::new); can be assigned before the map is encrypted.
+    // Added in the minor version 1.
+    #[serde(default)]
+    pub expiration_epoch: Option<u32>,
 }
 
 impl TreasureMap {
@@ -38,7 +138,9 @@ impl TreasureMap {
     ///
     /// Panics if `threshold` is set to 0,
     /// the number of assigned keyfrags is less than `threshold`,
-    /// or if the addresses in `assigned_kfrags` repeat.
+    /// if the addresses in `assigned_kfrags` repeat,
+    /// or if a key frag could not be encrypted
+    /// (see [`try_new`](Self::try_new) for a version propagating the latter).
     pub fn new(
         signer: &Signer,
         hrac: &HRAC,
@@ -46,6 +148,29 @@ impl TreasureMap {
         assigned_kfrags: impl IntoIterator<Item = (Address, (PublicKey, VerifiedKeyFrag))>,
         threshold: u8,
     ) -> Self {
+        match Self::try_new(
+            signer,
+            hrac,
+            policy_encrypting_key,
+            assigned_kfrags,
+            threshold,
+        ) {
+            Ok(result) => result,
+            Err(err) => panic!("{}", err),
+        }
+    }
+
+    /// Create a new treasure map for a collection of ursulas and kfrags,
+    /// returning an error if a key frag could not be encrypted.
+    ///
+    /// Panics under the same conditions as [`new`](Self::new) otherwise.
+    pub fn try_new(
+        signer: &Signer,
+        hrac: &HRAC,
+        policy_encrypting_key: &PublicKey,
+        assigned_kfrags: impl IntoIterator<Item = (Address, (PublicKey, VerifiedKeyFrag))>,
+        threshold: u8,
+    ) -> Result<Self, TreasureMapError> {
         // Panic here since violation of this condition indicates a bug on the caller's side.
         assert!(threshold != 0, "threshold must be non-zero");
 
@@ -54,7 +179,8 @@ impl TreasureMap {
         for (ursula_address, (ursula_encrypting_key, verified_kfrag)) in assigned_kfrags.into_iter()
         {
             let encrypted_kfrag =
-                EncryptedKeyFrag::new(signer, &ursula_encrypting_key, hrac, verified_kfrag);
+                EncryptedKeyFrag::try_new(signer, &ursula_encrypting_key, hrac, verified_kfrag)
+                    .map_err(|err| TreasureMapError::KeyFragEncryption(ursula_address, err))?;
             if destinations
                 .insert(ursula_address, encrypted_kfrag)
                 .is_some()
@@ -74,20 +200,264 @@ impl TreasureMap {
             "threshold cannot be larger than the total number of shares"
         );
 
-        Self {
+        Ok(Self {
             threshold,
             hrac: *hrac,
             destinations,
             policy_encrypting_key: *policy_encrypting_key,
             publisher_verifying_key: signer.verifying_key(),
+            expiration_epoch: None,
+        })
+    }
+
+    /// Checks the invariants enforced by [`new`](Self::new):
+    /// the threshold is non-zero, and not larger than the number of destinations.
+    ///
+    /// [`from_bytes`](ProtocolObject::from_bytes) only checks the structure of the data,
+    /// so this method should be called on treasure maps received from untrusted sources.
+    /// (The destination addresses are unique by construction,
+    /// since they are keys of a `BTreeMap`.)
+    pub fn validate(&self) -> Result<(), TreasureMapError> {
+        if self.threshold == 0 {
+            return Err(TreasureMapError::ZeroThreshold);
+        }
+        if self.destinations.len() < self.threshold as usize {
+            return Err(TreasureMapError::ThresholdTooLarge {
+                threshold: self.threshold,
+                shares: self.destinations.len(),
+            });
+        }
+        Ok(())
+    }
+
+    /// Returns a copy of the treasure map with the destination of the Ursula
+    /// with the given address removed (e.g. if that Ursula misbehaves),
+    /// along with its signature by `signer` (see [`sign`](Self::sign)).
+    ///
+    /// Note that the removed Ursula still holds its key frag,
+    /// so it has to be revoked separately (see [`RevocationOrder`]).
+    ///
+    /// Returns an error if the Ursula is not a destination of the map,
+    /// or if removing it would leave fewer destinations than the threshold.
+    pub fn without_ursula(
+        &self,
+        signer: &Signer,
+        address: &Address,
+    ) -> Result<(Self, Signature), TreasureMapError> {
+        if !self.destinations.contains_key(address) {
+            return Err(TreasureMapError::UnknownDestination(*address));
+        }
+        if self.destinations.len() - 1 < self.threshold as usize {
+            return Err(TreasureMapError::ThresholdTooLarge {
+                threshold: self.threshold,
+                shares: self.destinations.len() - 1,
+            });
+        }
+        let mut treasure_map = self.clone();
+        treasure_map.destinations.remove(address);
+        let signature = treasure_map.sign(signer);
+        Ok((treasure_map, signature))
+    }
+
+    /// Returns `true` if the policy has an expiration epoch, and it has been reached
+    /// at `now_epoch`.
+    pub fn is_expired(&self, now_epoch: u32) -> bool {
+        match self.expiration_epoch {
+            Some(expiration_epoch) => now_epoch >= expiration_epoch,
+            None => false,
         }
     }
 
     /// Encrypts the treasure map for Bob.
+    ///
+    /// The encrypted map is signed by `signer` together with `recipient_key`,
+    /// so it cannot be re-targeted to another recipient without a signer.
+    /// `signer` does not have to correspond to `publisher_verifying_key`
+    /// (e.g. a treasure map may be re-encrypted for Bob's new key by a different party);
+    /// the key of `signer` is the one that must be given to
+    /// [`EncryptedTreasureMap::decrypt`].
+    /// The key frags in `destinations` are signed separately and are not affected.
     pub fn encrypt(&self, signer: &Signer, recipient_key: &PublicKey) -> EncryptedTreasureMap {
         EncryptedTreasureMap::new(signer, recipient_key, self)
     }
 
+    /// Encrypts the treasure map for each of `recipient_keys`
+    /// (e.g. when several Bobs share access to the policy).
+    ///
+    /// Equivalent to calling [`encrypt`](Self::encrypt) for each recipient,
+    /// but the map is serialized only once, and the same bytes are used
+    /// both in the signed message and in the encrypted plaintext of each recipient.
+    /// Since the recipient key is a part of the signed message,
+    /// each of the returned maps is still signed separately,
+    /// and can only be decrypted and verified by its own recipient.
+    /// The results are in the same order as `recipient_keys`.
+    ///
+    /// Unlike [`encrypt`](Self::encrypt), returns an error if the encryption fails.
+    pub fn encrypt_for_many(
+        &self,
+        signer: &Signer,
+        recipient_keys: &[PublicKey],
+    ) -> Result<Vec<EncryptedTreasureMap>, EncryptionError> {
+        let minor_version = Self::minor_version(self);
+        let payload = Zeroizing::new(Self::unversioned_to_bytes(self));
+        let treasure_map_bytes = Zeroizing::new(with_header::<Self>(minor_version, &payload));
+        recipient_keys
+            .iter()
+            .map(|recipient_key| {
+                let message = AuthorizedTreasureMap::message_to_sign_from_bytes(
+                    recipient_key,
+                    &treasure_map_bytes,
+                );
+                let plaintext = AuthorizedTreasureMap::bytes_from_parts(
+                    &signer.sign(&message),
+                    minor_version,
+                    &payload,
+                );
+                EncryptedTreasureMap::try_from_plaintext(recipient_key, &plaintext)
+            })
+            .collect()
+    }
+
+    // The header is not included, so that the signature is not tied to the version
+    // the map is serialized with.
+    fn signing_bytes(&self) -> Zeroizing<Box<[u8]>> {
+        let payload = Zeroizing::new(Self::unversioned_to_bytes(self));
+        Zeroizing::new([TREASURE_MAP_SIGNATURE_TAG, &payload].concat().into())
+    }
+
+    /// Signs the treasure map, so that it can be authenticated
+    /// outside of an [`EncryptedTreasureMap`] (e.g. when stored decrypted)
+    /// with [`verify`](Self::verify).
+    pub fn sign(&self, signer: &Signer) -> Signature {
+        signer.sign(&self.signing_bytes())
+    }
+
+    /// Verifies a signature created by [`sign`](Self::sign).
+    pub fn verify(&self, signature: &Signature, publisher_verifying_key: &PublicKey) -> bool {
+        signature.verify(publisher_verifying_key, &self.signing_bytes())
+    }
+
+    /// Returns the bytes to be signed to authorize the map for `recipient_key`
+    /// when encrypting it with [`EncryptedTreasureMap::from_signature`]
+    /// (for use with an external signer).
+    ///
+    /// The message is the compressed recipient key (33 bytes)
+    /// followed by the output of [`to_bytes`](ProtocolObject::to_bytes).
+    /// Note that it contains the encrypted key frags.
+    pub fn authorization_signing_bytes(&self, recipient_key: &PublicKey) -> Box<[u8]> {
+        AuthorizedTreasureMap::message_to_sign(recipient_key, self)
+            .as_slice()
+            .into()
+    }
+
+    /// Returns the HRACs shared by treasure maps with differing contents in `maps`,
+    /// in ascending order.
+    ///
+    /// Since the HRAC is derived from the policy parameters, such collisions indicate
+    /// either a bug in the policy creation, or a forged treasure map.
+    pub fn hrac_collisions(maps: &[TreasureMap]) -> Vec<HRAC> {
+        let mut first_maps = BTreeMap::<HRAC, &TreasureMap>::new();
+        let mut collisions = BTreeSet::new();
+        for map in maps {
+            match first_maps.get(&map.hrac) {
+                Some(first_map) => {
+                    if *first_map != map {
+                        collisions.insert(map.hrac);
+                    }
+                }
+                None => {
+                    first_maps.insert(map.hrac, map);
+                }
+            }
+        }
+        collisions.into_iter().collect()
+    }
+
+    /// Returns the encrypted key frag assigned to the Ursula with the given address.
+    ///
+    /// An Ursula holding many policies can build an index for the reencryption requests
+    /// from all the treasure maps she received:
+    ///
+    /// ```ignore
+    /// let index: BTreeMap<HRAC, EncryptedKeyFrag> = treasure_maps
+    ///     .iter()
+    ///     .filter_map(|map| map.frag_for(&my_address).map(|ekfrag| (map.hrac, ekfrag.clone())))
+    ///     .collect();
+    /// ```
+    pub fn frag_for(&self, ursula_address: &Address) -> Option<&EncryptedKeyFrag> {
+        self.destinations.get(ursula_address)
+    }
+
+    /// Compares the destinations of the treasure map with the ones of `other`
+    /// (e.g. the previous map of a re-granted policy), so that only the affected Ursulas
+    /// need to be notified.
+    ///
+    /// `self` is treated as the new map, so `added` contains the addresses
+    /// present in `self`, but not in `other`.
+    /// Since the key frags are encrypted with a random nonce, a key frag re-encrypted
+    /// for the same Ursula is reported as `changed` even if the key frag itself is the same.
+    pub fn diff(&self, other: &TreasureMap) -> TreasureMapDiff {
+        let mut diff = TreasureMapDiff::default();
+        for (address, ekfrag) in self.destinations.iter() {
+            match other.destinations.get(address) {
+                Some(other_ekfrag) if other_ekfrag == ekfrag => {
+                    diff.unchanged.insert(*address);
+                }
+                Some(_) => {
+                    diff.changed.insert(*address);
+                }
+                None => {
+                    diff.added.insert(*address);
+                }
+            }
+        }
+        diff.removed = other
+            .destinations
+            .keys()
+            .filter(|address| !self.destinations.contains_key(address))
+            .copied()
+            .collect();
+        diff
+    }
+
+    /// Returns the addresses of the Ursulas the key frags are assigned to.
+    pub fn ursula_addresses(&self) -> BTreeSet<Address> {
+        self.destinations.keys().copied().collect()
+    }
+
+    /// Returns the number of additional Ursulas that need to be queried
+    /// to obtain `threshold` cfrags, given the addresses already queried in `retrieval_kit`.
+    ///
+    /// Only the queried addresses present in the treasure map are taken into account.
+    pub fn remaining_needed(&self, retrieval_kit: &RetrievalKit) -> usize {
+        let queried = retrieval_kit
+            .queried_addresses
+            .iter()
+            .filter(|address| self.destinations.contains_key(address))
+            .count();
+        (self.threshold as usize).saturating_sub(queried)
+    }
+
+    /// Returns the Ursulas that can be queried next for the retrieval described by
+    /// `retrieval_kit`, along with the number of cfrags still needed
+    /// (same as [`remaining_needed`](Self::remaining_needed)).
+    ///
+    /// The Ursulas recorded in the kit as failed
+    /// (see [`RetrievalKit::with_failed_addresses`]) are still candidates,
+    /// but are placed after the other ones.
+    pub fn retrieval_plan(&self, retrieval_kit: &RetrievalKit) -> RetrievalPlan {
+        let (failed, mut candidates): (Vec<Address>, Vec<Address>) = self
+            .destinations
+            .keys()
+            .filter(|address| !retrieval_kit.queried_addresses.contains(address))
+            .partition(|address| retrieval_kit.failed_addresses().contains(address));
+        candidates.extend(failed);
+        RetrievalPlan {
+            candidates,
+            remaining: self.remaining_needed(retrieval_kit),
+        }
+    }
+
     /// Makes revocation orders for all destinations in the treasure map.
     pub fn make_revocation_orders(&self, signer: &Signer) -> Vec<RevocationOrder> {
         self.destinations
@@ -95,6 +465,65 @@ impl TreasureMap {
             .map(|(address, ekfrag)| RevocationOrder::new(signer, address, ekfrag))
             .collect()
     }
+
+    /// Splits the treasure map into packets containing only the data
+    /// each of the destination Ursulas needs.
+    pub fn delivery_packets(&self) -> Vec<(Address, DeliveryPacket)> {
+        self.destinations
+            .iter()
+            .map(|(address, ekfrag)| {
+                let packet = DeliveryPacket {
+                    threshold: self.threshold,
+                    hrac: self.hrac,
+                    policy_encrypting_key: self.policy_encrypting_key,
+                    publisher_verifying_key: self.publisher_verifying_key,
+                    encrypted_kfrag: ekfrag.clone(),
+                };
+                (*address, packet)
+            })
+            .collect()
+    }
+}
+
+impl fmt::Display for TreasureMap {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "TreasureMap {{ hrac: {}, threshold: {}, destinations: {} entries }}",
+            self.hrac,
+            self.threshold,
+            self.destinations.len()
+        )
+    }
+}
+
+#[cfg(feature = "redacted-debug")]
+impl fmt::Debug for TreasureMap {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        fmt::Display::fmt(self, f)
+    }
+}
+
+// Serializes the treasure map without `expiration_epoch` if it is not set,
+// so that it has the same representation as in the version 3.0.
+struct CompatibleTreasureMap<'a>(&'a TreasureMap);
+
+impl Serialize for CompatibleTreasureMap<'_> {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        let treasure_map = self.0;
+        if treasure_map.expiration_epoch.is_some() {
+            treasure_map.serialize(serializer)
+        } else {
+            (
+                &treasure_map.threshold,
+                &treasure_map.hrac,
+                &treasure_map.destinations,
+                &treasure_map.policy_encrypting_key,
+                &treasure_map.publisher_verifying_key,
+            )
+                .serialize(serializer)
+        }
+    }
 }
 
 impl<'a> ProtocolObjectInner<'a> for TreasureMap {
@@ -103,15 +532,25 @@ impl<'a> ProtocolObjectInner<'a> for TreasureMap {
     }
 
     fn version() -> (u16, u16) {
-        (3, 0)
+        (3, 1)
     }
 
     fn unversioned_to_bytes(&self) -> Box<[u8]> {
-        messagepack_serialize(&self)
+        messagepack_serialize(&CompatibleTreasureMap(self))
+    }
+
+    fn minor_version(&self) -> u16 {
+        // `expiration_epoch` was added in the minor version 1.
+        u16::from(self.expiration_epoch.is_some())
+    }
+
+    fn supported_minor_versions() -> &'static [u16] {
+        &[0, 1]
     }
 
     fn unversioned_from_bytes(minor_version: u16, bytes: &[u8]) -> Option<Result<Self, String>> {
-        if minor_version == 0 {
+        // Version 3.0 does not have `expiration_epoch`, and it will be set to the default.
+        if minor_version <= 1 {
             Some(messagepack_deserialize(bytes))
         } else {
             None
@@ -121,16 +560,105 @@ impl<'a> ProtocolObjectInner<'a> for TreasureMap {
 
 impl<'a> ProtocolObject<'a> for TreasureMap {}
 
+/// The part of a treasure map intended for a single Ursula.
 #[derive(PartialEq, Debug, Clone, Serialize, Deserialize)]
+pub struct DeliveryPacket {
+    /// Threshold for successful re-encryption.
+    pub threshold: u8,
+    /// Policy HRAC.
+    pub hrac: HRAC,
+    /// A key to create encrypted messages under this policy.
+    pub policy_encrypting_key: PublicKey,
+    /// Publisher's verifying key.
+    pub publisher_verifying_key: PublicKey,
+    /// The key frag encrypted for the receiving Ursula.
+    pub encrypted_kfrag: EncryptedKeyFrag,
+}
+
+impl DeliveryPacket {
+    /// Decrypts the key frag and verifies that it was authorized by the publisher
+    /// for the policy identified by the packet's HRAC.
+    pub fn decrypt_kfrag(
+        &self,
+        sk: &SecretKey,
+    ) -> Result<VerifiedKeyFrag, AuthorizedDecryptionError> {
+        self.encrypted_kfrag
+            .decrypt(sk, &self.hrac, &self.publisher_verifying_key)
+    }
+}
+
+impl<'a> ProtocolObjectInner<'a> for DeliveryPacket {
+    fn brand() -> [u8; 4] {
+        *b"DPkt"
+    }
+
+    fn version() -> (u16, u16) {
+        (1, 0)
+    }
+
+    fn unversioned_to_bytes(&self) -> Box<[u8]> {
+        messagepack_serialize(&self)
+    }
+
+    fn unversioned_from_bytes(minor_version: u16, bytes: &[u8]) -> Option<Result<Self, String>> {
+        if minor_version == 0 {
+            Some(messagepack_deserialize(bytes))
+        } else {
+            None
+        }
+    }
+}
+
+impl<'a> ProtocolObject<'a> for DeliveryPacket {}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
 struct AuthorizedTreasureMap {
     signature: Signature,
     treasure_map: TreasureMap,
 }
 
 impl AuthorizedTreasureMap {
-    fn message_to_sign(recipient_key: &PublicKey, treasure_map: &TreasureMap) -> Vec<u8> {
-        let mut message = recipient_key.to_compressed_bytes().to_vec();
-        message.extend(treasure_map.to_bytes().iter());
+    fn message_to_sign(
+        recipient_key: &PublicKey,
+        treasure_map: &TreasureMap,
+    ) -> Zeroizing<Vec<u8>> {
+        // The message contains the serialized treasure map (with encrypted kfrags),
+        // so we zeroize it (and the intermediate buffer) after use.
+        let treasure_map_bytes = Zeroizing::new(treasure_map.to_bytes());
+        Self::message_to_sign_from_bytes(recipient_key, &treasure_map_bytes)
+    }
+
+    // Produces the same bytes as `to_bytes()` of the authorized map
+    // with the given signature and the treasure map
+    // (serialized with `unversioned_to_bytes()` and having the given minor version),
+    // so that the treasure map does not have to be cloned into the object.
+    fn bytes_from_parts(
+        signature: &Signature,
+        minor_version: u16,
+        treasure_map_payload: &[u8],
+    ) -> Zeroizing<Box<[u8]>> {
+        // A tuple of two elements is serialized as a MessagePack array.
+        let signature_bytes = messagepack_serialize(signature);
+        let mut payload = Zeroizing::new(Vec::with_capacity(
+            1 + signature_bytes.len() + treasure_map_payload.len(),
+        ));
+        payload.push(0x92); // array of 2 elements
+        payload.extend(signature_bytes.iter());
+        payload.extend(treasure_map_payload.iter());
+        Zeroizing::new(with_header::<Self>(minor_version, &payload))
+    }
+
+    fn message_to_sign_from_bytes(
+        recipient_key: &PublicKey,
+        treasure_map_bytes: &[u8],
+    ) -> Zeroizing<Vec<u8>> {
+        // Preallocating to avoid leaving the contents in memory on reallocation.
+        let key_bytes = recipient_key.to_compressed_bytes();
+        let mut message = Zeroizing::new(Vec::with_capacity(
+            key_bytes.len() + treasure_map_bytes.len(),
+        ));
+        message.extend(key_bytes.iter());
+        message.extend(treasure_map_bytes.iter());
         message
     }
 
@@ -144,12 +672,34 @@ impl AuthorizedTreasureMap {
         }
     }
 
+    // Extracts the serialized treasure map (with the header) from a serialized
+    // authorized treasure map, as it was signed by the publisher.
+    //
+    // Re-serializing the deserialized map is not guaranteed to produce the same bytes
+    // (e.g. if it was serialized by an implementation with a different latest minor version).
+    fn signed_treasure_map_bytes(auth_tmap_bytes: &[u8]) -> Option<Zeroizing<Box<[u8]>>> {
+        let (minor_version, mut payload) = split_header::<Self>(auth_tmap_bytes).ok()?;
+        // The payload is laid out as `[signature, treasure_map]`.
+        if decode::read_array_len(&mut payload).ok()? != 2 {
+            return None;
+        }
+        let signature_len = decode::read_bin_len(&mut payload).ok()? as usize;
+        let treasure_map_payload = payload.get(signature_len..)?;
+        // The treasure map has the same minor version as the authorized treasure map,
+        // see `minor_version()`.
+        Some(Zeroizing::new(with_header::<TreasureMap>(
+            minor_version,
+            treasure_map_payload,
+        )))
+    }
+
     fn verify(
         self,
         recipient_key: &PublicKey,
         publisher_verifying_key: &PublicKey,
+        treasure_map_bytes: &[u8],
     ) -> Option<TreasureMap> {
-        let message = Self::message_to_sign(recipient_key, &self.treasure_map);
+        let message = Self::message_to_sign_from_bytes(recipient_key, treasure_map_bytes);
         if !self.signature.verify(publisher_verifying_key, &message) {
             return None;
         }
@@ -157,21 +707,39 @@ impl AuthorizedTreasureMap {
     }
 }
 
+impl PartialEq for AuthorizedTreasureMap {
+    fn eq(&self, other: &Self) -> bool {
+        // Using a constant-time comparison since the object contains a signature.
+        messagepack_serialize(self)
+            .ct_eq(&messagepack_serialize(other))
+            .into()
+    }
+}
+
 impl<'a> ProtocolObjectInner<'a> for AuthorizedTreasureMap {
     fn brand() -> [u8; 4] {
         *b"AMap"
     }
 
     fn version() -> (u16, u16) {
-        (3, 0)
+        (3, 1)
     }
 
     fn unversioned_to_bytes(&self) -> Box<[u8]> {
-        messagepack_serialize(&self)
+        messagepack_serialize(&(&self.signature, CompatibleTreasureMap(&self.treasure_map)))
+    }
+
+    fn minor_version(&self) -> u16 {
+        <TreasureMap as ProtocolObjectInner>::minor_version(&self.treasure_map)
+    }
+
+    fn supported_minor_versions() -> &'static [u16] {
+        &[0, 1]
     }
 
     fn unversioned_from_bytes(minor_version: u16, bytes: &[u8]) -> Option<Result<Self, String>> {
-        if minor_version == 0 {
+        // Version 3.0 contains a treasure map without `expiration_epoch`.
+        if minor_version <= 1 {
             Some(messagepack_deserialize(bytes))
         } else {
             None
@@ -181,8 +749,26 @@ impl<'a> ProtocolObjectInner<'a> for AuthorizedTreasureMap {
 
 impl<'a> ProtocolObject<'a> for AuthorizedTreasureMap {}
 
+// Encrypts a serialized authorized object (a treasure map or a message) for the recipient.
+fn encrypt_authorized(recipient_key: &PublicKey, plaintext: &[u8]) -> (Capsule, Box<[u8]>) {
+    match encrypt(recipient_key, plaintext) {
+        Ok(result) => result,
+        Err(err) => match err {
+            // For now this is the only error that can happen during encryption,
+            // and there's really no point in propagating it.
+            EncryptionError::PlaintextTooLarge => panic!("encryption failed - out of memory?"),
+        },
+    }
+}
+
 /// A treasure map encrypted for Bob.
-#[derive(PartialEq, Debug, Clone, Serialize, Deserialize)]
+///
+/// The verifying key of the signer (needed for [`decrypt`](Self::decrypt))
+/// is intentionally not included in the clear: encrypted maps can be stored and passed around
+/// by third parties, and exposing it would link the map to the publisher.
+/// It has to be obtained through the same channel as the map itself.
+#[derive(PartialEq, Clone, Serialize, Deserialize)]
+#[cfg_attr(not(feature = "redacted-debug"), derive(Debug))]
 pub struct EncryptedTreasureMap {
     capsule: Capsule,
     #[serde(with = "serde_bytes::as_base64")]
@@ -195,37 +781,119 @@ impl EncryptedTreasureMap {
         // Most probably it is an overkill, unless it can be used somehow
         // for Ursula-to-Ursula "baton passing".
 
-        // TODO: `publisher` here can be different from the one in TreasureMap, it seems.
-        // Do we ever cross-check them? Do we want to enforce them to be the same?
+        // Note: `signer` here can be different from the publisher in TreasureMap.
+        // This is intentional (see the docs for `TreasureMap::encrypt()`):
+        // the signature only authorizes the map for the recipient, while the key frags
+        // are authorized by the publisher independently, and are checked by Ursulas.
 
         let authorized_tmap = AuthorizedTreasureMap::new(signer, recipient_key, treasure_map);
-        let (capsule, ciphertext) = match encrypt(recipient_key, &authorized_tmap.to_bytes()) {
-            Ok(result) => result,
-            Err(err) => match err {
-                // For now this is the only error that can happen during encryption,
-                // and there's really no point in propagating it.
-                EncryptionError::PlaintextTooLarge => panic!("encryption failed - out of memory?"),
-            },
+        Self::from_authorized(recipient_key, &authorized_tmap)
+    }
+
+    /// Encrypts the treasure map for `recipient_key` using a signature produced externally
+    /// over [`TreasureMap::authorization_signing_bytes`]
+    /// (e.g. by a hardware security module).
+    ///
+    /// Returns an error if the signature cannot be verified with `signer_verifying_key`
+    /// (the key that must be given to [`decrypt`](Self::decrypt)).
+    pub fn from_signature(
+        treasure_map: &TreasureMap,
+        recipient_key: &PublicKey,
+        signature: &Signature,
+        signer_verifying_key: &PublicKey,
+    ) -> Result<Self, VerificationError> {
+        let message = AuthorizedTreasureMap::message_to_sign(recipient_key, treasure_map);
+        if !signature.verify(signer_verifying_key, &message) {
+            return Err(VerificationError);
+        }
+        let authorized_tmap = AuthorizedTreasureMap {
+            signature: signature.clone(),
+            treasure_map: treasure_map.clone(),
         };
+        Ok(Self::from_authorized(recipient_key, &authorized_tmap))
+    }
+
+    fn from_authorized(recipient_key: &PublicKey, authorized_tmap: &AuthorizedTreasureMap) -> Self {
+        let (capsule, ciphertext) = encrypt_authorized(recipient_key, &authorized_tmap.to_bytes());
         Self {
             capsule,
             ciphertext,
         }
     }
 
+    // Takes the serialized `AuthorizedTreasureMap`.
+    fn try_from_plaintext(
+        recipient_key: &PublicKey,
+        plaintext: &[u8],
+    ) -> Result<Self, EncryptionError> {
+        let (capsule, ciphertext) = encrypt(recipient_key, plaintext)?;
+        Ok(Self {
+            capsule,
+            ciphertext,
+        })
+    }
+
+    /// Creates an encrypted treasure map from its components
+    /// (e.g. the ones previously obtained from [`capsule`](Self::capsule)
+    /// and [`ciphertext`](Self::ciphertext)).
+    ///
+    /// No verification is performed until the map is decrypted.
+    pub fn from_parts(capsule: Capsule, ciphertext: Box<[u8]>) -> Self {
+        Self {
+            capsule,
+            ciphertext,
+        }
+    }
+
+    /// Returns the capsule of the encrypted treasure map.
+    pub fn capsule(&self) -> &Capsule {
+        &self.capsule
+    }
+
+    /// Returns the ciphertext of the encrypted treasure map.
+    pub fn ciphertext(&self) -> &[u8] {
+        &self.ciphertext
+    }
+
     /// Decrypts and verifies the treasure map.
+    ///
+    /// `publisher_verifying_key` is the verifying key of the signer used to encrypt the map,
+    /// which may differ from the one in the map itself.
     pub fn decrypt(
         &self,
         sk: &SecretKey,
         publisher_verifying_key: &PublicKey,
-    ) -> Result<TreasureMap, DecryptionError> {
+    ) -> Result<TreasureMap, AuthorizedDecryptionError> {
         let auth_tmap_bytes = decrypt_original(sk, &self.capsule, &self.ciphertext)
-            .map_err(DecryptionError::DecryptionFailed)?;
+            .map_err(AuthorizedDecryptionError::DecryptionFailed)?;
         let auth_tmap = AuthorizedTreasureMap::from_bytes(&auth_tmap_bytes)
-            .map_err(DecryptionError::DeserializationFailed)?;
+            .map_err(AuthorizedDecryptionError::DeserializationFailed)?;
+        let treasure_map_bytes = AuthorizedTreasureMap::signed_treasure_map_bytes(&auth_tmap_bytes)
+            .ok_or(AuthorizedDecryptionError::VerificationFailed)?;
         auth_tmap
-            .verify(&sk.public_key(), publisher_verifying_key)
-            .ok_or(DecryptionError::VerificationFailed)
+            .verify(
+                &sk.public_key(),
+                publisher_verifying_key,
+                &treasure_map_bytes,
+            )
+            .ok_or(AuthorizedDecryptionError::VerificationFailed)
+    }
+}
+
+impl fmt::Display for EncryptedTreasureMap {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "EncryptedTreasureMap {{ ciphertext: {} bytes }}",
+            self.ciphertext.len()
+        )
+    }
+}
+
+#[cfg(feature = "redacted-debug")]
+impl fmt::Debug for EncryptedTreasureMap {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        fmt::Display::fmt(self, f)
     }
 }
 
@@ -252,3 +920,634 @@ impl<'a> ProtocolObjectInner<'a> for EncryptedTreasureMap {
 }
 
 impl<'a> ProtocolObject<'a> for EncryptedTreasureMap {}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+struct AuthorizedMessage {
+    signature: Signature,
+    #[serde(with = "serde_bytes::as_base64")]
+    message: Box<[u8]>,
+}
+
+impl AuthorizedMessage {
+    fn message_to_sign(recipient_key: &PublicKey, message: &[u8]) -> Zeroizing<Vec<u8>> {
+        // The brand is included so that the signature cannot be substituted
+        // for the one of an `AuthorizedTreasureMap`
+        // (the serialized treasure map starts with its own brand).
+        let tagged_message = [EncryptedMessage::brand().as_ref(), message].concat();
+        AuthorizedTreasureMap::message_to_sign_from_bytes(
+            recipient_key,
+            &Zeroizing::new(tagged_message),
+        )
+    }
+
+    fn new(signer: &Signer, recipient_key: &PublicKey, message: &[u8]) -> Self {
+        let signature = signer.sign(&Self::message_to_sign(recipient_key, message));
+        Self {
+            signature,
+            message: message.into(),
+        }
+    }
+
+    fn verify(
+        self,
+        recipient_key: &PublicKey,
+        sender_verifying_key: &PublicKey,
+    ) -> Option<Box<[u8]>> {
+        let message = Self::message_to_sign(recipient_key, &self.message);
+        if !self.signature.verify(sender_verifying_key, &message) {
+            return None;
+        }
+        Some(self.message)
+    }
+}
+
+impl<'a> ProtocolObjectInner<'a> for AuthorizedMessage {
+    fn brand() -> [u8; 4] {
+        *b"AMsg"
+    }
+
+    fn version() -> (u16, u16) {
+        (1, 0)
+    }
+
+    fn unversioned_to_bytes(&self) -> Box<[u8]> {
+        messagepack_serialize(&self)
+    }
+
+    fn unversioned_from_bytes(minor_version: u16, bytes: &[u8]) -> Option<Result<Self, String>> {
+        if minor_version == 0 {
+            Some(messagepack_deserialize(bytes))
+        } else {
+            None
+        }
+    }
+}
+
+impl<'a> ProtocolObject<'a> for AuthorizedMessage {}
+
+/// A small message signed by the sender and encrypted for the recipient
+/// (e.g. for control messages between a publisher and a recipient).
+///
+/// The message is protected the same way as an [`EncryptedTreasureMap`]:
+/// it is signed together with the recipient's key, so it cannot be re-targeted
+/// to another recipient, and the signature is only revealed to the recipient.
+#[derive(PartialEq, Clone, Serialize, Deserialize)]
+#[cfg_attr(not(feature = "redacted-debug"), derive(Debug))]
+pub struct EncryptedMessage {
+    capsule: Capsule,
+    #[serde(with = "serde_bytes::as_base64")]
+    ciphertext: Box<[u8]>,
+}
+
+impl EncryptedMessage {
+    /// Signs the message with `signer` and encrypts it for `recipient_key`.
+    pub fn new(signer: &Signer, recipient_key: &PublicKey, message: &[u8]) -> Self {
+        let authorized_message = AuthorizedMessage::new(signer, recipient_key, message);
+        let (capsule, ciphertext) =
+            encrypt_authorized(recipient_key, &authorized_message.to_bytes());
+        Self {
+            capsule,
+            ciphertext,
+        }
+    }
+
+    /// Decrypts the message and verifies that it was signed by `sender_verifying_key`.
+    pub fn decrypt(
+        &self,
+        sk: &SecretKey,
+        sender_verifying_key: &PublicKey,
+    ) -> Result<Box<[u8]>, AuthorizedDecryptionError> {
+        let auth_message_bytes = decrypt_original(sk, &self.capsule, &self.ciphertext)
+            .map_err(AuthorizedDecryptionError::DecryptionFailed)?;
+        let auth_message = AuthorizedMessage::from_bytes(&auth_message_bytes)
+            .map_err(AuthorizedDecryptionError::DeserializationFailed)?;
+        auth_message
+            .verify(&sk.public_key(), sender_verifying_key)
+            .ok_or(AuthorizedDecryptionError::VerificationFailed)
+    }
+}
+
+impl fmt::Display for EncryptedMessage {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "EncryptedMessage {{ ciphertext: {} bytes }}",
+            self.ciphertext.len()
+        )
+    }
+}
+
+#[cfg(feature = "redacted-debug")]
+impl fmt::Debug for EncryptedMessage {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        fmt::Display::fmt(self, f)
+    }
+}
+
+impl<'a> ProtocolObjectInner<'a> for EncryptedMessage {
+    fn brand() -> [u8; 4] {
+        *b"EMsg"
+    }
+
+    fn version() -> (u16, u16) {
+        (1, 0)
+    }
+
+    fn unversioned_to_bytes(&self) -> Box<[u8]> {
+        messagepack_serialize(&self)
+    }
+
+    fn unversioned_from_bytes(minor_version: u16, bytes: &[u8]) -> Option<Result<Self, String>> {
+        if minor_version == 0 {
+            Some(messagepack_deserialize(bytes))
+        } else {
+            None
+        }
+    }
+}
+
+impl<'a> ProtocolObject<'a> for EncryptedMessage {}
+
+#[cfg(test)]
+mod tests {
+    use alloc::vec::Vec;
+
+    use rand::SeedableRng;
+    use rand_chacha::ChaCha20Rng;
+    use umbral_pre::{decrypt_original, generate_kfrags, SecretKey, Signer};
+
+    use super::{
+        AuthorizedTreasureMap, DeliveryPacket, EncryptedMessage, EncryptedTreasureMap, TreasureMap,
+        TreasureMapError,
+    };
+    use crate::address::Address;
+    use crate::hrac::HRAC;
+    use crate::key_frag::AuthorizedDecryptionError;
+    use crate::message_kit::MessageKit;
+    use crate::retrieval_kit::RetrievalKit;
+    use crate::versioning::{
+        messagepack_serialize, sign_detached, verify_detached, ProtocolObject, ProtocolObjectInner,
+    };
+
+    fn make_treasure_map() -> TreasureMap {
+        let delegating_sk = SecretKey::random();
+        let publisher = Signer::new(SecretKey::random());
+        let bob_pk = SecretKey::random().public_key();
+        let hrac = HRAC::new(&publisher.verifying_key(), &bob_pk, b"label");
+        let kfrags = generate_kfrags(&delegating_sk, &bob_pk, &publisher, 2, 3, true, true);
+        let assigned_kfrags = kfrags.iter().enumerate().map(|(i, kfrag)| {
+            (
+                Address::new(&[i as u8; Address::SIZE]),
+                (SecretKey::random().public_key(), kfrag.clone()),
+            )
+        });
+        TreasureMap::new(
+            &publisher,
+            &hrac,
+            &delegating_sk.public_key(),
+            assigned_kfrags,
+            2,
+        )
+    }
+
+    #[test]
+    fn remaining_needed() {
+        let treasure_map = make_treasure_map();
+        let capsule = MessageKit::new(&treasure_map.policy_encrypting_key, b"message", None)
+            .capsule()
+            .clone();
+        let kit = |indices: &[u8]| {
+            RetrievalKit::new(
+                &capsule,
+                indices.iter().map(|i| Address::new(&[*i; Address::SIZE])),
+                None,
+            )
+        };
+
+        assert_eq!(treasure_map.remaining_needed(&kit(&[])), 2);
+        assert_eq!(treasure_map.remaining_needed(&kit(&[0])), 1);
+        // Addresses outside of the treasure map do not count
+        assert_eq!(treasure_map.remaining_needed(&kit(&[0, 10, 11])), 1);
+        assert_eq!(treasure_map.remaining_needed(&kit(&[0, 1, 2])), 0);
+    }
+
+    #[test]
+    fn retrieval_plan() {
+        let treasure_map = make_treasure_map();
+        let capsule = MessageKit::new(&treasure_map.policy_encrypting_key, b"message", None)
+            .capsule()
+            .clone();
+        let address = |i: u8| Address::new(&[i; Address::SIZE]);
+
+        let plan = treasure_map.retrieval_plan(&RetrievalKit::new(&capsule, [], None));
+        assert_eq!(plan.candidates, [address(0), address(1), address(2)]);
+        assert_eq!(plan.remaining, 2);
+        assert!(!plan.is_complete());
+        assert!(!plan.is_exhausted());
+
+        let kit = RetrievalKit::new(&capsule, [address(1), address(10)], None)
+            .with_failed_addresses([address(0)]);
+        let plan = treasure_map.retrieval_plan(&kit);
+        // Failed Ursulas go last
+        assert_eq!(plan.candidates, [address(2), address(0)]);
+        assert_eq!(plan.remaining, 1);
+
+        let kit = RetrievalKit::new(&capsule, [address(0), address(1)], None);
+        let plan = treasure_map.retrieval_plan(&kit);
+        assert_eq!(plan.candidates, [address(2)]);
+        assert!(plan.is_complete());
+
+        let mut treasure_map = treasure_map;
+        treasure_map.destinations.remove(&address(2));
+        let kit =
+            RetrievalKit::new(&capsule, [address(0)], None).with_failed_addresses([address(1)]);
+        assert!(!treasure_map.retrieval_plan(&kit).is_exhausted());
+        treasure_map.destinations.remove(&address(1));
+        assert!(treasure_map.retrieval_plan(&kit).is_exhausted());
+    }
+
+    #[test]
+    fn sign_and_verify() {
+        let mut treasure_map = make_treasure_map();
+        let signer = Signer::new(SecretKey::random());
+        let signature = treasure_map.sign(&signer);
+
+        assert!(treasure_map.verify(&signature, &signer.verifying_key()));
+        assert!(!treasure_map.verify(&signature, &SecretKey::random().public_key()));
+
+        // Not interchangeable with a detached signature of the serialized map
+        let detached_signature = sign_detached(&signer, &treasure_map);
+        assert!(!treasure_map.verify(&detached_signature, &signer.verifying_key()));
+        assert!(!verify_detached(
+            &treasure_map,
+            &signature,
+            &signer.verifying_key()
+        ));
+
+        treasure_map.expiration_epoch = Some(123);
+        assert!(!treasure_map.verify(&signature, &signer.verifying_key()));
+    }
+
+    #[test]
+    fn external_authorization() {
+        let treasure_map = make_treasure_map();
+        let signer = Signer::new(SecretKey::random());
+        let bob_sk = SecretKey::random();
+
+        let message = treasure_map.authorization_signing_bytes(&bob_sk.public_key());
+        let signature = signer.sign(&message);
+        let encrypted = EncryptedTreasureMap::from_signature(
+            &treasure_map,
+            &bob_sk.public_key(),
+            &signature,
+            &signer.verifying_key(),
+        )
+        .unwrap();
+        assert_eq!(
+            encrypted.decrypt(&bob_sk, &signer.verifying_key()).unwrap(),
+            treasure_map
+        );
+
+        assert!(EncryptedTreasureMap::from_signature(
+            &treasure_map,
+            &SecretKey::random().public_key(),
+            &signature,
+            &signer.verifying_key(),
+        )
+        .is_err());
+    }
+
+    #[test]
+    fn hrac_collisions() {
+        let treasure_map = make_treasure_map();
+        let another_map = make_treasure_map();
+
+        let mut colliding_map = treasure_map.clone();
+        colliding_map.expiration_epoch = Some(123);
+
+        assert!(TreasureMap::hrac_collisions(&[
+            treasure_map.clone(),
+            another_map.clone(),
+            treasure_map.clone()
+        ])
+        .is_empty());
+        assert_eq!(
+            TreasureMap::hrac_collisions(&[treasure_map.clone(), another_map, colliding_map]),
+            [treasure_map.hrac]
+        );
+    }
+
+    #[test]
+    fn frag_for() {
+        let treasure_map = make_treasure_map();
+        let address = Address::new(&[1; Address::SIZE]);
+        assert!(treasure_map.frag_for(&address).is_some());
+        assert!(treasure_map
+            .frag_for(&Address::new(&[10; Address::SIZE]))
+            .is_none());
+    }
+
+    #[test]
+    fn validate() {
+        let mut treasure_map = make_treasure_map();
+        assert_eq!(treasure_map.validate(), Ok(()));
+
+        treasure_map.threshold = 4;
+        let restored = TreasureMap::from_bytes(&treasure_map.to_bytes()).unwrap();
+        assert_eq!(
+            restored.validate(),
+            Err(TreasureMapError::ThresholdTooLarge {
+                threshold: 4,
+                shares: 3
+            })
+        );
+
+        treasure_map.threshold = 0;
+        assert_eq!(
+            treasure_map.validate(),
+            Err(TreasureMapError::ZeroThreshold)
+        );
+    }
+
+    #[test]
+    fn expiration() {
+        let mut treasure_map = make_treasure_map();
+        assert!(!treasure_map.is_expired(u32::MAX));
+
+        treasure_map.expiration_epoch = Some(1000);
+        assert!(!treasure_map.is_expired(999));
+        assert!(treasure_map.is_expired(1000));
+
+        let restored = TreasureMap::from_bytes(&treasure_map.to_bytes()).unwrap();
+        assert_eq!(restored.expiration_epoch, Some(1000));
+    }
+
+    #[test]
+    fn version_3_0_compatibility() {
+        let treasure_map = make_treasure_map();
+
+        // Version 3.0 serialization, without `expiration_epoch`
+        let mut bytes = b"TMap".to_vec();
+        bytes.extend(3u16.to_be_bytes());
+        bytes.extend(0u16.to_be_bytes());
+        bytes.extend(
+            messagepack_serialize(&(
+                treasure_map.threshold,
+                treasure_map.hrac,
+                &treasure_map.destinations,
+                treasure_map.policy_encrypting_key,
+                treasure_map.publisher_verifying_key,
+            ))
+            .iter(),
+        );
+
+        let restored = TreasureMap::from_bytes(&bytes).unwrap();
+        assert_eq!(restored, treasure_map);
+        assert_eq!(restored.expiration_epoch, None);
+
+        // Maps without `expiration_epoch` are still serialized as version 3.0,
+        // so that they can be read by the implementations not supporting the field.
+        assert_eq!(treasure_map.to_bytes(), bytes.into_boxed_slice());
+        assert_eq!(treasure_map.wire_version(), (3, 0));
+
+        let mut treasure_map = treasure_map;
+        treasure_map.expiration_epoch = Some(1000);
+        assert_eq!(treasure_map.wire_version(), (3, 1));
+        assert_eq!(
+            TreasureMap::version_of(&treasure_map.to_bytes()),
+            Some((3, 1))
+        );
+    }
+
+    #[test]
+    fn decrypt_version_3_0() {
+        // An encrypted treasure map created by the version of the library
+        // where `TreasureMap` and `AuthorizedTreasureMap` were at version 3.0.
+        let bytes = hex::decode(concat!(
+            "454d6170000300009293c42103b5c38cabcfec1511f8408873bc97ef69b0e1e026aa8a72cd8b03ab5cfae06a",
+            "34c4210383557f127fe5db1e90b4fed18889f0d79e9e87ddee1cdd2f637781ba408adb3bc420dcbd459a4474",
+            "f9476738359894add84d6885bfed4606bc62502fbcc8d3f59b74c502fa6f80367824f6c5f74361bd0d7a7d89",
+            "7323cbe545c3c282eeae8745130c76e1b411daca20ccb510b2a601250682b840b38aed83e351bf24be19a180",
+            "e9fbc3edf5b7ae7ee9c947d8881500464235426632be3231ecdd463dd5fe22cc6796a17f7eda5e8000ee9ae2",
+            "d14be37a26dd91537914b167b6f7ddff36f170cdea73637db1eb0c7b7565c58c892aefbed22da9bf2eb0709d",
+            "7c5d0f9ea8efe13fabe2a3e8707fc416b21a5c1e02443ca9dcd0aa121a9895ad736b9c3f58424bcf81806a8a",
+            "dfaeb0f1c199ddb561151a8d018d688672d01d05434a760bf09a7d3dcd8124e4f991a7695f34c99e62184f4c",
+            "960c6e92cb0b6de38995f8fc95687a6390603a8c53bf74254bc8ee248fa6f6832c487a5e15cd36ab4dd7a9c7",
+            "d775642100d56dcb4cecba97b4a331fa9a11819e57f2356cc71de2f12423a6f1beb6584b430b4fa7da46c73b",
+            "989f9a24f721ef6cbb39c46f54b88be3229617eb6958964f858c189c0fc617f98413f5224de1e13ec36628d8",
+            "9b532ad022bb6f23dd51c18c1de1de9172a8a896d7f7e6ae6dc7c3a77fa09b913af0aa626fff8bbca972cf42",
+            "4f12db02e264ab7f1179c18d4253a2e4e95d649626c1ed575f7eaa85b5fd14f4a5128a89b5984acd754c3e0b",
+            "f424ac3afd94f9eb4303694d0497a43ebc131200eacc1ea768a8c5cc19c8f8f57eb173837d8f4cd4087d76fd",
+            "30a83399f9b92b918ecd0c1b3e7c26685090ecdf4e00f1c4e9a8f19af2b6a5005d848d3fdfed732ee7c8962d",
+            "2a4b4a24478ebad109c06e80a6252463a0d2cd097e2da0ef0397fa63bfe464219d00fcc726dba2bf4e64e530",
+            "1558e1caa9eb35dafb039f0a7867fce5e659b90450f9ad325784b52d93c220e32c63546a6a793c75ea0582f6",
+            "d7a7a968660ef009e5aef790ac7b02a06a22931261b50fe5751fe1f95f14e8a4b40bf27d64a35a73c10b5366",
+            "8238fe58eb2891ca989fc2aec77bc16170c8905329d21496066c211a365ae6006f840084142a1d926a693a66",
+            "4cf47c5a6d9c7f5e13f00a5bac18f44c40a7f199b5a89105ffa3d9adab24ff6540b902225983d945cde0d6",
+        ))
+        .unwrap();
+
+        let key = |seed: u64| SecretKey::random_with_rng(&mut ChaCha20Rng::seed_from_u64(seed));
+        let publisher_verifying_key = key(2).public_key();
+        let bob_sk = key(3);
+
+        let encrypted = EncryptedTreasureMap::from_bytes(&bytes).unwrap();
+        let treasure_map = encrypted
+            .decrypt(&bob_sk, &publisher_verifying_key)
+            .unwrap();
+        assert_eq!(treasure_map.threshold, 1);
+        assert_eq!(treasure_map.policy_encrypting_key, key(1).public_key());
+        assert_eq!(
+            treasure_map.publisher_verifying_key,
+            publisher_verifying_key
+        );
+        assert_eq!(
+            treasure_map.ursula_addresses(),
+            [Address::new(&[1; Address::SIZE])].into()
+        );
+        assert_eq!(treasure_map.expiration_epoch, None);
+
+        // A map re-encrypted by the current version can be read by the older ones
+        let publisher = Signer::new(key(2));
+        let encrypted = treasure_map.encrypt(&publisher, &bob_sk.public_key());
+        let auth_tmap_bytes =
+            decrypt_original(&bob_sk, &encrypted.capsule, &encrypted.ciphertext).unwrap();
+        assert_eq!(
+            AuthorizedTreasureMap::version_of(&auth_tmap_bytes),
+            Some((3, 0))
+        );
+
+        // The signature is checked against the map as it was serialized by the publisher
+        let mut treasure_map = treasure_map;
+        treasure_map.expiration_epoch = Some(1000);
+        let encrypted = treasure_map.encrypt(&publisher, &bob_sk.public_key());
+        let restored = encrypted
+            .decrypt(&bob_sk, &publisher.verifying_key())
+            .unwrap();
+        assert_eq!(restored.expiration_epoch, Some(1000));
+    }
+
+    #[test]
+    fn delivery_packets() {
+        let delegating_sk = SecretKey::random();
+        let publisher = Signer::new(SecretKey::random());
+        let bob_pk = SecretKey::random().public_key();
+        let ursula_sks = (0..3).map(|_| SecretKey::random()).collect::<Vec<_>>();
+
+        let hrac = HRAC::new(&publisher.verifying_key(), &bob_pk, b"label");
+        let kfrags = generate_kfrags(&delegating_sk, &bob_pk, &publisher, 2, 3, true, true);
+        let assigned_kfrags = (0..3).map(|i| {
+            (
+                Address::new(&[i as u8; Address::SIZE]),
+                (ursula_sks[i].public_key(), kfrags[i].clone()),
+            )
+        });
+        let treasure_map = TreasureMap::new(
+            &publisher,
+            &hrac,
+            &delegating_sk.public_key(),
+            assigned_kfrags,
+            2,
+        );
+
+        let packets = treasure_map.delivery_packets();
+        assert_eq!(packets.len(), 3);
+        for (i, (address, packet)) in packets.iter().enumerate() {
+            assert_eq!(address, &Address::new(&[i as u8; Address::SIZE]));
+            assert_eq!(&packet.encrypted_kfrag, &treasure_map.destinations[address]);
+
+            // mimic sending the packet over the wire
+            let packet = DeliveryPacket::from_bytes(&packet.to_bytes()).unwrap();
+            let kfrag = packet.decrypt_kfrag(&ursula_sks[i]).unwrap();
+            assert_eq!(kfrag, kfrags[i]);
+        }
+
+        // A packet with a substituted HRAC does not pass verification
+        let mut packet = packets[0].1.clone();
+        packet.hrac = HRAC::new(&publisher.verifying_key(), &bob_pk, b"another label");
+        assert!(packet.decrypt_kfrag(&ursula_sks[0]).is_err());
+    }
+
+    #[test]
+    fn encrypt_for_many() {
+        let treasure_map = make_treasure_map();
+        let signer = Signer::new(SecretKey::random());
+        let bob_sks = [SecretKey::random(), SecretKey::random()];
+        let bob_pks = bob_sks.iter().map(|sk| sk.public_key()).collect::<Vec<_>>();
+
+        let encrypted = treasure_map.encrypt_for_many(&signer, &bob_pks).unwrap();
+        assert_eq!(encrypted.len(), 2);
+
+        for (sk, encrypted_map) in bob_sks.iter().zip(encrypted.iter()) {
+            assert_eq!(
+                encrypted_map.decrypt(sk, &signer.verifying_key()).unwrap(),
+                treasure_map
+            );
+        }
+
+        // Each map can only be decrypted by its own recipient
+        assert!(encrypted[0]
+            .decrypt(&bob_sks[1], &signer.verifying_key())
+            .is_err());
+
+        assert!(treasure_map
+            .encrypt_for_many(&signer, &[])
+            .unwrap()
+            .is_empty());
+
+        // The plaintext assembled from the serialized map is the same
+        // as the serialized authorized map, for both minor versions
+        let mut treasure_map = treasure_map;
+        for expiration_epoch in [None, Some(1000)] {
+            treasure_map.expiration_epoch = expiration_epoch;
+            let authorized_tmap = AuthorizedTreasureMap::new(&signer, &bob_pks[0], &treasure_map);
+            let plaintext = AuthorizedTreasureMap::bytes_from_parts(
+                &authorized_tmap.signature,
+                treasure_map.minor_version(),
+                &treasure_map.unversioned_to_bytes(),
+            );
+            assert_eq!(*plaintext, authorized_tmap.to_bytes());
+        }
+    }
+
+    #[test]
+    fn diff() {
+        let old_map = make_treasure_map();
+        let address = |i: u8| Address::new(&[i; Address::SIZE]);
+
+        let mut new_map = old_map.clone();
+        new_map.destinations.remove(&address(0));
+        let moved_ekfrag = new_map.destinations[&address(2)].clone();
+        new_map.destinations.insert(address(10), moved_ekfrag);
+        let replaced_ekfrag = old_map.destinations[&address(2)].clone();
+        new_map.destinations.insert(address(1), replaced_ekfrag);
+
+        let diff = new_map.diff(&old_map);
+        assert_eq!(diff.added, [address(10)].into());
+        assert_eq!(diff.removed, [address(0)].into());
+        assert_eq!(diff.unchanged, [address(2)].into());
+        assert_eq!(diff.changed, [address(1)].into());
+
+        let diff = old_map.diff(&old_map);
+        assert!(diff.added.is_empty() && diff.removed.is_empty() && diff.changed.is_empty());
+        assert_eq!(diff.unchanged, old_map.ursula_addresses());
+    }
+
+    #[test]
+    fn without_ursula() {
+        let treasure_map = make_treasure_map();
+        let signer = Signer::new(SecretKey::random());
+        let address = |i: u8| Address::new(&[i; Address::SIZE]);
+
+        let (reduced_map, signature) = treasure_map.without_ursula(&signer, &address(1)).unwrap();
+        assert_eq!(
+            reduced_map.ursula_addresses(),
+            [address(0), address(2)].into()
+        );
+        assert!(reduced_map.validate().is_ok());
+        assert!(reduced_map.verify(&signature, &signer.verifying_key()));
+        assert!(!treasure_map.verify(&signature, &signer.verifying_key()));
+
+        assert_eq!(
+            treasure_map
+                .without_ursula(&signer, &address(10))
+                .unwrap_err(),
+            TreasureMapError::UnknownDestination(address(10))
+        );
+        assert_eq!(
+            reduced_map
+                .without_ursula(&signer, &address(0))
+                .unwrap_err(),
+            TreasureMapError::ThresholdTooLarge {
+                threshold: 2,
+                shares: 1
+            }
+        );
+    }
+
+    #[test]
+    fn encrypted_message() {
+        let sender = Signer::new(SecretKey::random());
+        let recipient_sk = SecretKey::random();
+        let recipient_pk = recipient_sk.public_key();
+
+        let encrypted = EncryptedMessage::new(&sender, &recipient_pk, b"baton");
+        let restored = EncryptedMessage::from_bytes(&encrypted.to_bytes()).unwrap();
+        assert_eq!(
+            restored
+                .decrypt(&recipient_sk, &sender.verifying_key())
+                .unwrap()
+                .as_ref(),
+            b"baton"
+        );
+
+        assert!(matches!(
+            encrypted.decrypt(&recipient_sk, &SecretKey::random().public_key()),
+            Err(AuthorizedDecryptionError::VerificationFailed)
+        ));
+        assert!(matches!(
+            encrypted.decrypt(&SecretKey::random(), &sender.verifying_key()),
+            Err(AuthorizedDecryptionError::DecryptionFailed(_))
+        ));
+    }
+}
@@ -3,10 +3,56 @@ use alloc::format;
 use alloc::string::String;
 use alloc::vec::Vec;
 use core::fmt;
+use core::marker::PhantomData;
 
-use serde::{Deserialize, Serialize};
+use serde::de::{Error as _, SeqAccess, Visitor};
+use serde::ser::SerializeTuple;
+use serde::{Deserialize, Deserializer, Serialize, Serializer};
+use sha2::{digest::Update, Digest, Sha256};
+use umbral_pre::{PublicKey, Signature, Signer};
 
+/// The way structs are encoded in MessagePack.
+///
+/// Deserialization accepts both encodings, so the choice only affects the serialization.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) enum StructEncoding {
+    /// Structs are encoded as arrays of field values, in the order of declaration.
+    ///
+    /// This is the most compact encoding, used for all the signed objects
+    /// (and, in fact, all the protocol objects except the ones listed for `Map`).
+    /// A new field can only be added at the end, with a minor version bump
+    /// if it has a default value (see e.g. `TreasureMap::expiration_epoch`),
+    /// and only if the struct is not signed as a whole.
+    Array,
+    /// Structs are encoded as maps from field names to values.
+    ///
+    /// Fields unknown to the deserializer are ignored, and missing fields marked with
+    /// `#[serde(default)]` take the default values, so optional fields can be added
+    /// without a version bump. Meant for unsigned auxiliary objects,
+    /// currently only used by `FleetStateDiff`.
+    ///
+    /// Note that the encoding applies to the nested structs as well.
+    Map,
+}
+
+/// Serializes the object into MessagePack, encoding structs as arrays
+/// (see [`StructEncoding::Array`]).
+///
+/// The output must be canonical: deserializing it and serializing the result again
+/// must produce the same bytes, since the serialized objects are often signed.
+/// This means that only ordered collections (e.g. `BTreeMap` instead of `HashMap`)
+/// can be used in serializable types.
 pub(crate) fn messagepack_serialize<T>(obj: &T) -> Box<[u8]>
+where
+    T: Serialize,
+{
+    messagepack_serialize_with(obj, StructEncoding::Array)
+}
+
+/// Serializes the object into MessagePack, using the given encoding for structs.
+///
+/// The same canonicity requirements as for [`messagepack_serialize`] apply.
+pub(crate) fn messagepack_serialize_with<T>(obj: &T, encoding: StructEncoding) -> Box<[u8]>
 where
     T: Serialize,
 {
@@ -18,7 +64,11 @@ where
     // Panic on serialization error.
     // For this library, a serialization error will certainly indicate
     // some irrecoverable logical problem, so there is no sense in propagating it.
-    rmp_serde::to_vec(obj)
+    let result = match encoding {
+        StructEncoding::Array => rmp_serde::to_vec(obj),
+        StructEncoding::Map => rmp_serde::to_vec_named(obj),
+    };
+    result
         .map(|vec| vec.into_boxed_slice())
         .expect("Error serializing into MessagePack")
 }
@@ -27,6 +77,8 @@ pub(crate) fn messagepack_deserialize<'a, T>(bytes: &'a [u8]) -> Result<T, Strin
 where
     T: Deserialize<'a>,
 {
+    // Note: the input is untrusted, so this must not panic.
+    // `rmp_serde` limits the nesting depth, and returns an error if it is exceeded.
     rmp_serde::from_slice(bytes).map_err(|err| format!("{}", err))
 }
 
@@ -53,40 +105,105 @@ impl ProtocolObjectHeader {
         }
     }
 
+    fn check<'a, T>(&self) -> Result<(), DeserializationError>
+    where
+        T: ProtocolObjectInner<'a>,
+    {
+        let reference_header = Self::from_type::<T>();
+
+        if self.brand != reference_header.brand {
+            return Err(DeserializationError::IncorrectHeader {
+                expected: reference_header.brand,
+                received: self.brand,
+            });
+        }
+
+        if self.major_version != reference_header.major_version {
+            return Err(DeserializationError::MajorVersionMismatch {
+                expected: reference_header.major_version,
+                received: self.major_version,
+            });
+        }
+
+        if self.minor_version > reference_header.minor_version {
+            return Err(DeserializationError::UnsupportedMinorVersion {
+                expected: reference_header.minor_version,
+                received: self.minor_version,
+            });
+        }
+
+        Ok(())
+    }
+
     fn from_type<'a, T>() -> Self
     where
         T: ProtocolObjectInner<'a>,
     {
-        let (major, minor) = T::version();
+        let (_major, minor) = T::version();
+        Self::with_minor_version::<T>(minor)
+    }
+
+    fn with_minor_version<'a, T>(minor_version: u16) -> Self
+    where
+        T: ProtocolObjectInner<'a>,
+    {
+        let (major, _minor) = T::version();
         Self {
             brand: T::brand(),
             major_version: major,
-            minor_version: minor,
+            minor_version,
         }
     }
 }
 
+/// Errors that can happen during the deserialization of a [`ProtocolObject`].
 #[derive(Debug)]
 pub enum DeserializationError {
+    /// The bytestring is too short to contain the header.
     TooShort {
+        /// The minimum expected length.
         expected: usize,
+        /// The length of the given bytestring.
         received: usize,
     },
+    /// The brand in the header belongs to a different object type.
     IncorrectHeader {
+        /// The brand of the type being deserialized.
         expected: [u8; 4],
+        /// The brand in the header.
         received: [u8; 4],
     },
+    /// The major version in the header differs from the supported one.
     MajorVersionMismatch {
+        /// The supported major version.
         expected: u16,
+        /// The major version in the header.
         received: u16,
     },
+    /// The minor version in the header is newer than the supported one.
     UnsupportedMinorVersion {
+        /// The latest supported minor version.
         expected: u16,
+        /// The minor version in the header.
         received: u16,
     },
+    /// The header is correct, but the payload could not be deserialized.
     BadPayload {
+        /// The error message from the deserializer.
+        error_msg: String,
+    },
+    /// Failed to read the data from a reader.
+    ReadFailed {
+        /// The error message from the reader.
         error_msg: String,
     },
+    /// The payload contains more items than allowed.
+    TooLarge {
+        /// The maximum allowed number of items.
+        limit: usize,
+        /// The number of items in the payload.
+        received: usize,
+    },
 }
 
 impl fmt::Display for DeserializationError {
@@ -115,6 +232,12 @@ impl fmt::Display for DeserializationError {
             Self::BadPayload { error_msg } => {
                 write!(f, "payload deserialization failed: {}", error_msg)
             }
+            Self::ReadFailed { error_msg } => write!(f, "reading failed: {}", error_msg),
+            Self::TooLarge { limit, received } => write!(
+                f,
+                "payload too large: expected at most {} items, got {}",
+                limit, received
+            ),
         }
     }
 }
@@ -135,7 +258,43 @@ pub trait ProtocolObjectInner<'a>: Serialize + Deserialize<'a> {
 
     fn unversioned_to_bytes(&self) -> Box<[u8]>;
 
+    /// Returns the minor version `unversioned_to_bytes()` serializes the object with.
+    ///
+    /// Types with fields added in a minor version override it to return
+    /// the lowest minor version that can represent the object,
+    /// and omit the unset newer fields in `unversioned_to_bytes()`,
+    /// so that the objects not using them can still be read
+    /// by the implementations supporting only the older versions.
+    fn minor_version(&self) -> u16 {
+        Self::version().1
+    }
+
+    /// Returns an upper estimate of the length of `unversioned_to_bytes()` output.
+    ///
+    /// The default implementation is exact, but requires serializing the object,
+    /// so it should be overridden for types that can be large.
+    fn unversioned_size_hint(&self) -> usize {
+        self.unversioned_to_bytes().len()
+    }
+
+    /// Returns the minor versions (for the current major version)
+    /// that `unversioned_from_bytes()` can deserialize.
+    ///
+    /// Must be overridden if the type supports minor versions other than 0.
+    fn supported_minor_versions() -> &'static [u16] {
+        &[0]
+    }
+
     fn unversioned_from_bytes(minor_version: u16, bytes: &'a [u8]) -> Option<Result<Self, String>>;
+
+    /// Checks the invariants of a deserialized object that its `serde` implementation
+    /// does not enforce.
+    ///
+    /// Called after the payload is deserialized, whatever the encoding
+    /// (see [`ProtocolObject::from_bytes`] and `ProtocolObject::from_bincode_bytes`).
+    fn validate(&self) -> Result<(), String> {
+        Ok(())
+    }
 }
 
 /// This is a versioned protocol object.
@@ -149,60 +308,718 @@ pub trait ProtocolObject<'a>: ProtocolObjectInner<'a> {
         <Self as ProtocolObjectInner>::version()
     }
 
+    /// Returns the version the object is serialized with by [`to_bytes`](Self::to_bytes),
+    /// for use in generic code holding an instance.
+    ///
+    /// The minor version may be lower than the one returned by [`version`](Self::version)
+    /// if the object does not use the fields added in the later minor versions.
+    fn wire_version(&self) -> (u16, u16) {
+        let (major, _minor) = <Self as ProtocolObject>::version();
+        (major, self.minor_version())
+    }
+
+    /// The minor versions of the object (for the major version returned by
+    /// [`version`](Self::version)) that can be deserialized by the current implementation.
+    fn supported_minor_versions() -> &'static [u16] {
+        <Self as ProtocolObjectInner>::supported_minor_versions()
+    }
+
     /// Serializes the object.
     fn to_bytes(&self) -> Box<[u8]> {
-        let header_bytes = ProtocolObjectHeader::from_type::<Self>().to_bytes();
-        let unversioned_bytes = Self::unversioned_to_bytes(self);
+        with_header::<Self>(self.minor_version(), &Self::unversioned_to_bytes(self))
+    }
+
+    /// Returns a SHA-256 digest of the serialized object (see [`to_bytes`](Self::to_bytes)).
+    ///
+    /// Since the serialization is canonical, the digest can be used as a content ID.
+    /// It only changes if the version of the object does
+    /// (in which case the object is serialized differently).
+    fn content_id(&self) -> [u8; 32] {
+        Sha256::new().chain(self.to_bytes()).finalize().into()
+    }
 
-        let mut result = Vec::with_capacity(header_bytes.len() + unversioned_bytes.len());
-        result.extend(header_bytes);
-        result.extend(unversioned_bytes.iter());
-        result.into_boxed_slice()
+    /// Serializes the object using `bincode` for the payload
+    /// (the header is the same as for [`to_bytes`](Self::to_bytes)).
+    ///
+    /// `bincode` is not self-describing, so, unlike with `to_bytes()`,
+    /// the unset fields cannot be omitted: all the fields are serialized,
+    /// and the header always has the latest minor version
+    /// (and not [`wire_version`](Self::wire_version)).
+    /// For the same reason, only the objects serialized with the latest minor version
+    /// can be read by [`from_bincode_bytes`](Self::from_bincode_bytes).
+    #[cfg(feature = "bincode")]
+    fn to_bincode_bytes(&self) -> Box<[u8]> {
+        // Same as with MessagePack, a serialization error indicates a logical problem.
+        let payload = bincode::serialize(self).expect("Error serializing into bincode");
+        let (_major, minor) = <Self as ProtocolObject>::version();
+        with_header::<Self>(minor, &payload)
+    }
+
+    /// Returns an estimate of the length of `to_bytes()` output.
+    ///
+    /// The estimate may be larger than the actual length, but never smaller,
+    /// so it can be used to preallocate buffers.
+    fn serialized_size_hint(&self) -> usize {
+        // The header is 8 bytes long, see `ProtocolObjectHeader::to_bytes()`.
+        8 + self.unversioned_size_hint()
+    }
+
+    /// Returns the version `(major, minor)` encoded in the header of a serialized object,
+    /// without deserializing the object itself.
+    ///
+    /// Returns `None` if `bytes` is too short to contain a header,
+    /// or if the header belongs to a different object type.
+    fn version_of(bytes: &[u8]) -> Option<(u16, u16)> {
+        let header_bytes: &[u8; 8] = bytes.get(..8)?.try_into().ok()?;
+        let header = ProtocolObjectHeader::from_bytes(header_bytes);
+        if header.brand != <Self as ProtocolObjectInner>::brand() {
+            return None;
+        }
+        Some((header.major_version, header.minor_version))
     }
 
     /// Attempts to deserialize the object.
     fn from_bytes(bytes: &'a [u8]) -> Result<Self, DeserializationError> {
-        if bytes.len() < 8 {
-            return Err(DeserializationError::TooShort {
-                expected: 8,
-                received: bytes.len(),
+        let (minor_version, payload) = split_header::<Self>(bytes)?;
+        deserialize_payload::<Self>(minor_version, payload)
+    }
+
+    /// Attempts to deserialize the object serialized with
+    /// [`to_bincode_bytes`](Self::to_bincode_bytes).
+    ///
+    /// The object is checked the same way as with [`from_bytes`](Self::from_bytes).
+    #[cfg(feature = "bincode")]
+    fn from_bincode_bytes(bytes: &'a [u8]) -> Result<Self, DeserializationError> {
+        let (minor_version, payload) = split_header::<Self>(bytes)?;
+        let (_major, latest_minor_version) = <Self as ProtocolObject>::version();
+        if minor_version != latest_minor_version {
+            return Err(DeserializationError::BadPayload {
+                error_msg: format!(
+                    "bincode payloads can only be read for the minor version {}, got {}",
+                    latest_minor_version, minor_version
+                ),
             });
         }
-        let mut header_bytes = [0u8; 8];
-        header_bytes.copy_from_slice(&bytes[..8]);
-        let header = ProtocolObjectHeader::from_bytes(&header_bytes);
+        let object: Self =
+            bincode::deserialize(payload).map_err(|err| DeserializationError::BadPayload {
+                error_msg: format!("{}", err),
+            })?;
+        object
+            .validate()
+            .map_err(|error_msg| DeserializationError::BadPayload { error_msg })?;
+        Ok(object)
+    }
+}
 
-        let reference_header = ProtocolObjectHeader::from_type::<Self>();
+pub(crate) fn with_header<'a, T>(minor_version: u16, unversioned_bytes: &[u8]) -> Box<[u8]>
+where
+    T: ProtocolObjectInner<'a>,
+{
+    let header_bytes = ProtocolObjectHeader::with_minor_version::<T>(minor_version).to_bytes();
 
-        if header.brand != reference_header.brand {
-            return Err(DeserializationError::IncorrectHeader {
-                expected: reference_header.brand,
-                received: header.brand,
-            });
+    let mut result = Vec::with_capacity(header_bytes.len() + unversioned_bytes.len());
+    result.extend(header_bytes);
+    result.extend(unversioned_bytes.iter());
+    result.into_boxed_slice()
+}
+
+/// Attempts to read and deserialize a protocol object from `reader`.
+///
+/// The header is read and checked first, so an object of a different type
+/// or an unsupported version is rejected without reading the rest of the data.
+#[cfg(feature = "std")]
+pub fn read_protocol_object<T>(mut reader: impl std::io::Read) -> Result<T, DeserializationError>
+where
+    T: for<'a> ProtocolObject<'a>,
+{
+    let read_failed = |err: std::io::Error| DeserializationError::ReadFailed {
+        error_msg: format!("{}", err),
+    };
+
+    let mut header_bytes = [0u8; 8];
+    reader.read_exact(&mut header_bytes).map_err(read_failed)?;
+    let header = ProtocolObjectHeader::from_bytes(&header_bytes);
+    header.check::<T>()?;
+
+    let mut payload = Vec::new();
+    reader.read_to_end(&mut payload).map_err(read_failed)?;
+    deserialize_payload::<T>(header.minor_version, &payload)
+}
+
+/// Checks the header of a serialized object of type `T`,
+/// and returns the minor version from it along with the rest of the bytes.
+pub(crate) fn split_header<'a, T>(bytes: &[u8]) -> Result<(u16, &[u8]), DeserializationError>
+where
+    T: ProtocolObjectInner<'a>,
+{
+    if bytes.len() < 8 {
+        return Err(DeserializationError::TooShort {
+            expected: 8,
+            received: bytes.len(),
+        });
+    }
+    let mut header_bytes = [0u8; 8];
+    header_bytes.copy_from_slice(&bytes[..8]);
+    let header = ProtocolObjectHeader::from_bytes(&header_bytes);
+    header.check::<T>()?;
+    Ok((header.minor_version, &bytes[8..]))
+}
+
+fn deserialize_payload<'a, T>(
+    minor_version: u16,
+    bytes: &'a [u8],
+) -> Result<T, DeserializationError>
+where
+    T: ProtocolObjectInner<'a>,
+{
+    let result = match T::unversioned_from_bytes(minor_version, bytes) {
+        Some(result) => result,
+        // The type must support all minor versions below or equal to the current one,
+        // otherwise it should be the major version change.
+        // This is a bug, so we panic here.
+        None => panic!("minor version {} is not supported", minor_version),
+    };
+
+    result
+        .and_then(|object| object.validate().map(|_| object))
+        .map_err(|msg| DeserializationError::BadPayload { error_msg: msg })
+}
+
+/// An object-safe counterpart of [`ProtocolObject`], allowing objects of different types
+/// to be stored together (e.g. as `Box<dyn ErasedProtocolObject>`).
+///
+/// Implemented for every [`ProtocolObject`].
+/// Since the method names are the same, it is meant to be used through a trait object;
+/// for concrete types, use [`ProtocolObject`] methods.
+pub trait ErasedProtocolObject {
+    /// Returns the unique tag of the object's type.
+    fn brand(&self) -> [u8; 4];
+
+    /// Returns the version of the object as a tuple `(major, minor)`
+    /// supported by the current implementation.
+    fn version(&self) -> (u16, u16);
+
+    /// Serializes the object (see [`ProtocolObject::to_bytes`]).
+    fn to_bytes(&self) -> Box<[u8]>;
+}
+
+impl<T> ErasedProtocolObject for T
+where
+    T: for<'a> ProtocolObject<'a>,
+{
+    fn brand(&self) -> [u8; 4] {
+        <T as ProtocolObjectInner>::brand()
+    }
+
+    fn version(&self) -> (u16, u16) {
+        <T as ProtocolObjectInner>::version()
+    }
+
+    fn to_bytes(&self) -> Box<[u8]> {
+        <T as ProtocolObject>::to_bytes(self)
+    }
+}
+
+// Prefixed to the serialized object before signing, so that detached signatures
+// cannot be confused with the signatures made by the protocol objects themselves.
+const DETACHED_SIGNATURE_TAG: &[u8] = b"DSig";
+
+fn detached_signing_bytes(object: &(impl ErasedProtocolObject + ?Sized)) -> Box<[u8]> {
+    [DETACHED_SIGNATURE_TAG, &object.to_bytes()].concat().into()
+}
+
+/// Signs the serialized protocol object (see [`ProtocolObject::to_bytes`]),
+/// so that the object and the signature can be stored separately.
+///
+/// The signature covers the brand and the version of the object as well,
+/// and is only valid for this serialization.
+pub fn sign_detached(signer: &Signer, object: &(impl ErasedProtocolObject + ?Sized)) -> Signature {
+    signer.sign(&detached_signing_bytes(object))
+}
+
+/// Verifies a signature created by [`sign_detached`].
+pub fn verify_detached(
+    object: &(impl ErasedProtocolObject + ?Sized),
+    signature: &Signature,
+    verifying_key: &PublicKey,
+) -> bool {
+    signature.verify(verifying_key, &detached_signing_bytes(object))
+}
+
+/// A wrapper for a protocol object that serializes it together with its brand and version
+/// when used with any `serde` serializer (as opposed to [`ProtocolObject::to_bytes`],
+/// which always uses MessagePack).
+///
+/// The object is serialized as a tuple `(brand, major_version, minor_version, object)`.
+/// All the fields of the object are serialized, so the version is always the latest one
+/// (see [`ProtocolObject::version`]), unlike with [`ProtocolObject::to_bytes`].
+#[derive(PartialEq, Eq, Debug, Clone)]
+pub struct Versioned<T>(pub T);
+
+impl<T> Serialize for Versioned<T>
+where
+    T: for<'a> ProtocolObject<'a>,
+{
+    fn serialize<S>(&self, serializer: S) -> Result<S::Ok, S::Error>
+    where
+        S: Serializer,
+    {
+        let header = ProtocolObjectHeader::from_type::<T>();
+        let mut tuple = serializer.serialize_tuple(4)?;
+        tuple.serialize_element(&header.brand)?;
+        tuple.serialize_element(&header.major_version)?;
+        tuple.serialize_element(&header.minor_version)?;
+        tuple.serialize_element(&self.0)?;
+        tuple.end()
+    }
+}
+
+impl<'de, T> Deserialize<'de> for Versioned<T>
+where
+    T: ProtocolObjectInner<'de>,
+{
+    fn deserialize<D>(deserializer: D) -> Result<Self, D::Error>
+    where
+        D: Deserializer<'de>,
+    {
+        struct VersionedVisitor<T>(PhantomData<T>);
+
+        impl<'de, T> Visitor<'de> for VersionedVisitor<T>
+        where
+            T: ProtocolObjectInner<'de>,
+        {
+            type Value = Versioned<T>;
+
+            fn expecting(&self, formatter: &mut fmt::Formatter<'_>) -> fmt::Result {
+                formatter.write_str("a tuple of brand, major version, minor version, and object")
+            }
+
+            fn visit_seq<A>(self, mut seq: A) -> Result<Self::Value, A::Error>
+            where
+                A: SeqAccess<'de>,
+            {
+                let header = ProtocolObjectHeader {
+                    brand: seq
+                        .next_element()?
+                        .ok_or_else(|| A::Error::invalid_length(0, &self))?,
+                    major_version: seq
+                        .next_element()?
+                        .ok_or_else(|| A::Error::invalid_length(1, &self))?,
+                    minor_version: seq
+                        .next_element()?
+                        .ok_or_else(|| A::Error::invalid_length(2, &self))?,
+                };
+                // Check the header before deserializing the object itself.
+                header.check::<T>().map_err(A::Error::custom)?;
+                let object = seq
+                    .next_element()?
+                    .ok_or_else(|| A::Error::invalid_length(3, &self))?;
+                Ok(Versioned(object))
+            }
         }
 
-        if header.major_version != reference_header.major_version {
-            return Err(DeserializationError::MajorVersionMismatch {
-                expected: reference_header.major_version,
-                received: header.major_version,
-            });
+        deserializer.deserialize_tuple(4, VersionedVisitor(PhantomData))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use alloc::boxed::Box;
+    use alloc::vec::Vec;
+
+    use ferveo::api::DkgPublicKey;
+    use rand::{Rng, SeedableRng};
+    use rand_chacha::ChaCha20Rng;
+    use umbral_pre::{encrypt, generate_kfrags, reencrypt, SecretKey, Signer};
+
+    use serde::{Deserialize, Serialize};
+
+    use super::{
+        messagepack_deserialize, messagepack_serialize, messagepack_serialize_with, sign_detached,
+        verify_detached, ProtocolObject, StructEncoding, Versioned,
+    };
+    use crate::node_metadata::tests::make_node;
+    use crate::{
+        encrypt_for_dkg, AccessControlPolicy, Address, BatchRetrievalKit, Conditions, Context,
+        EncryptedKeyFrag, EncryptedMessage, FleetStateChecksum, FleetStateDiff, MessageKit,
+        MetadataRequest, MetadataResponse, MetadataResponsePayload, NodeMetadataStub,
+        ReencryptionRequest, ReencryptionResponse, RetrievalKit, ThresholdMessageKit, TreasureMap,
+        HRAC,
+    };
+
+    fn assert_canonical<T>(obj: &T)
+    where
+        T: for<'a> ProtocolObject<'a>,
+    {
+        let bytes = obj.to_bytes();
+        let restored = T::from_bytes(&bytes).unwrap();
+        assert_eq!(restored.to_bytes(), bytes);
+
+        assert_rejects_malformed::<T>(&bytes);
+    }
+
+    // Checks that `from_bytes()` does not panic on malformed input.
+    fn assert_rejects_malformed<T>(bytes: &[u8])
+    where
+        T: for<'a> ProtocolObject<'a>,
+    {
+        let mut rng = ChaCha20Rng::seed_from_u64(123);
+
+        // A MessagePack-encoded object cannot be valid if it is truncated.
+        for length in 0..bytes.len() {
+            assert!(T::from_bytes(&bytes[..length]).is_err());
         }
 
-        if header.minor_version > reference_header.minor_version {
-            return Err(DeserializationError::UnsupportedMinorVersion {
-                expected: reference_header.minor_version,
-                received: header.minor_version,
-            });
+        // Corrupted bytes may or may not result in a valid object,
+        // but deserialization should not panic.
+        for _ in 0..64 {
+            let mut corrupted = bytes.to_vec();
+            let position = rng.gen_range(0..corrupted.len());
+            corrupted[position] ^= rng.gen_range(1..=u8::MAX);
+            let _ = T::from_bytes(&corrupted);
+        }
+
+        // Random payloads with a correct header
+        for _ in 0..64 {
+            let mut random = bytes[..8].to_vec();
+            let length = rng.gen_range(0..bytes.len());
+            random.extend((0..length).map(|_| rng.gen::<u8>()));
+            let _ = T::from_bytes(&random);
+        }
+    }
+
+    #[test]
+    fn supported_minor_versions() {
+        assert_eq!(MessageKit::supported_minor_versions(), &[0]);
+        assert_eq!(TreasureMap::supported_minor_versions(), &[0, 1]);
+        assert_eq!(MetadataResponse::supported_minor_versions(), &[0, 1, 2, 3]);
+        assert_eq!(RetrievalKit::supported_minor_versions(), &[0, 1, 2]);
+
+        let mut bytes = MessageKit::new(&SecretKey::random().public_key(), b"message", None)
+            .to_bytes()
+            .to_vec();
+        bytes[6..8].copy_from_slice(&1u16.to_be_bytes());
+        assert!(MessageKit::from_bytes(&bytes).is_err());
+    }
+
+    #[test]
+    fn erased_protocol_object() {
+        let message_kit = MessageKit::new(&SecretKey::random().public_key(), b"message", None);
+        let retrieval_kit = RetrievalKit::from_message_kit(&message_kit);
+        let expected = [message_kit.to_bytes(), retrieval_kit.to_bytes()];
+
+        // Not importing `ErasedProtocolObject` to avoid the ambiguity with `ProtocolObject`;
+        // its methods are still available on the trait objects.
+        let objects: [Box<dyn super::ErasedProtocolObject>; 2] =
+            [Box::new(message_kit), Box::new(retrieval_kit)];
+
+        assert_eq!(objects[0].brand(), *b"MKit");
+        assert_eq!(objects[1].brand(), *b"RKit");
+        assert_eq!(
+            objects[1].version(),
+            <RetrievalKit as ProtocolObject<'_>>::version()
+        );
+        for (object, bytes) in objects.iter().zip(expected.iter()) {
+            assert_eq!(&object.to_bytes(), bytes);
+        }
+    }
+
+    #[test]
+    fn wire_version() {
+        fn describe<'a, T: ProtocolObject<'a>>(object: &T) -> (u16, u16) {
+            object.wire_version()
+        }
+
+        let message_kit = MessageKit::new(&SecretKey::random().public_key(), b"message", None);
+        assert_eq!(
+            describe(&message_kit),
+            <MessageKit as ProtocolObject<'_>>::version()
+        );
+        assert_eq!(
+            MessageKit::version_of(&message_kit.to_bytes()),
+            Some(message_kit.wire_version())
+        );
+    }
+
+    #[test]
+    fn content_id() {
+        let message_kit = MessageKit::new(&SecretKey::random().public_key(), b"message", None);
+        let restored = MessageKit::from_bytes(&message_kit.to_bytes()).unwrap();
+        assert_eq!(message_kit.content_id(), restored.content_id());
+
+        let another_kit = MessageKit::new(&SecretKey::random().public_key(), b"message", None);
+        assert_ne!(message_kit.content_id(), another_kit.content_id());
+    }
+
+    #[test]
+    fn version_of() {
+        let message_kit = MessageKit::new(&SecretKey::random().public_key(), b"message", None);
+        let bytes = message_kit.to_bytes();
+
+        assert_eq!(
+            MessageKit::version_of(&bytes),
+            Some(<MessageKit as ProtocolObject<'_>>::version())
+        );
+        assert_eq!(MessageKit::version_of(&bytes[..7]), None);
+        assert_eq!(RetrievalKit::version_of(&bytes), None);
+
+        // Only the header is read
+        let mut header = bytes[..8].to_vec();
+        header.extend(b"not a payload");
+        assert_eq!(
+            MessageKit::version_of(&header),
+            Some(<MessageKit as ProtocolObject<'_>>::version())
+        );
+    }
+
+    #[cfg(feature = "std")]
+    #[test]
+    fn read_protocol_object() {
+        use super::{read_protocol_object, DeserializationError};
+
+        let message_kit = MessageKit::new(&SecretKey::random().public_key(), b"message", None);
+        let bytes = message_kit.to_bytes();
+
+        let restored: MessageKit = read_protocol_object(bytes.as_ref()).unwrap();
+        assert_eq!(restored, message_kit);
+
+        // The payload is not read if the header is incorrect
+        let mut reader = bytes.as_ref();
+        assert!(matches!(
+            read_protocol_object::<RetrievalKit>(&mut reader),
+            Err(DeserializationError::IncorrectHeader { .. })
+        ));
+        assert_eq!(reader.len(), bytes.len() - 8);
+
+        assert!(matches!(
+            read_protocol_object::<MessageKit>(&bytes[..4]),
+            Err(DeserializationError::ReadFailed { .. })
+        ));
+    }
+
+    #[test]
+    fn versioned() {
+        let message_kit = MessageKit::new(&SecretKey::random().public_key(), b"message", None);
+
+        // Using `rmp_serde` directly, bypassing `ProtocolObject::to_bytes()`
+        let bytes = rmp_serde::to_vec(&Versioned(message_kit.clone())).unwrap();
+        let restored: Versioned<MessageKit> = rmp_serde::from_slice(&bytes).unwrap();
+        assert_eq!(restored.0, message_kit);
+
+        assert!(rmp_serde::from_slice::<Versioned<RetrievalKit>>(&bytes).is_err());
+    }
+
+    #[cfg(feature = "bincode")]
+    #[test]
+    fn bincode() {
+        let conditions = Conditions::new("abcd");
+        let message_kit = MessageKit::new(
+            &SecretKey::random().public_key(),
+            b"message",
+            Some(&conditions),
+        );
+        let bytes = message_kit.to_bincode_bytes();
+        assert_eq!(bytes[..8], message_kit.to_bytes()[..8]);
+        assert_eq!(MessageKit::from_bincode_bytes(&bytes).unwrap(), message_kit);
+
+        let nodes = [make_node(1, 1)];
+        let response = MetadataResponse::new(
+            &Signer::new(SecretKey::random()),
+            &MetadataResponsePayload::new(1, &nodes),
+        );
+        let bytes = response.to_bincode_bytes();
+        assert_eq!(
+            MetadataResponse::from_bincode_bytes(&bytes).unwrap(),
+            response
+        );
+
+        // All the fields are serialized, so only the latest minor version can be read
+        let mut bytes = bytes.to_vec();
+        bytes[6..8].copy_from_slice(&0u16.to_be_bytes());
+        assert!(MetadataResponse::from_bincode_bytes(&bytes).is_err());
+    }
+
+    #[test]
+    fn canonical_serialization() {
+        let delegating_sk = SecretKey::random();
+        let policy_encrypting_key = delegating_sk.public_key();
+        let publisher = Signer::new(SecretKey::random());
+        let bob_sk = SecretKey::random();
+        let bob_pk = bob_sk.public_key();
+        let ursula_sk = SecretKey::random();
+        let ursula_signer = Signer::new(SecretKey::random());
+        let conditions = Conditions::new("abcd");
+
+        let hrac = HRAC::new(&publisher.verifying_key(), &bob_pk, b"label");
+        let kfrags = generate_kfrags(&delegating_sk, &bob_pk, &publisher, 2, 3, true, true);
+        let assigned_kfrags = kfrags
+            .iter()
+            .enumerate()
+            .map(|(i, kfrag)| {
+                (
+                    Address::new(&[i as u8; Address::SIZE]),
+                    (ursula_sk.public_key(), kfrag.clone()),
+                )
+            })
+            .collect::<Vec<_>>();
+
+        let treasure_map = TreasureMap::new(
+            &publisher,
+            &hrac,
+            &policy_encrypting_key,
+            assigned_kfrags,
+            2,
+        );
+        assert_canonical(&treasure_map);
+        assert_canonical(&treasure_map.encrypt(&publisher, &bob_pk));
+        assert_canonical(&EncryptedMessage::new(&publisher, &bob_pk, b"message"));
+        assert_canonical(&treasure_map.make_revocation_orders(&publisher)[0]);
+
+        let ekfrag = EncryptedKeyFrag::new(
+            &publisher,
+            &ursula_sk.public_key(),
+            &hrac,
+            kfrags[0].clone(),
+        );
+        assert_canonical(&ekfrag);
+
+        let message_kit = MessageKit::new(&policy_encrypting_key, b"message", Some(&conditions));
+        assert_canonical(&message_kit);
+
+        let retrieval_kit = RetrievalKit::new(
+            &message_kit.capsule,
+            [
+                Address::new(&[2; Address::SIZE]),
+                Address::new(&[1; Address::SIZE]),
+            ],
+            Some(&conditions),
+        );
+        assert_canonical(&retrieval_kit);
+        assert_canonical(&BatchRetrievalKit::new(
+            &[
+                message_kit.capsule.clone(),
+                MessageKit::new(&policy_encrypting_key, b"another message", None).capsule,
+            ],
+            [Address::new(&[2; Address::SIZE])],
+            None,
+        ));
+
+        let (capsule, _ciphertext) = encrypt(&policy_encrypting_key, b"message").unwrap();
+        assert_canonical(&ReencryptionRequest::new(
+            core::slice::from_ref(&capsule),
+            &hrac,
+            &ekfrag,
+            &publisher.verifying_key(),
+            &bob_pk,
+            Some(&conditions),
+            Some(&Context::new("efgh")),
+        ));
+        let vcfrag = reencrypt(&capsule, kfrags[0].clone());
+        assert_canonical(&ReencryptionResponse::new(
+            &ursula_signer,
+            [(&capsule, vcfrag)],
+        ));
+
+        let nodes = [make_node(1, 1), make_node(2, 2)];
+        assert_canonical(&nodes[0]);
+        assert_canonical(&NodeMetadataStub::new(
+            &Signer::new(SecretKey::random()),
+            &nodes[0].payload,
+        ));
+        assert_canonical(&FleetStateDiff::new(&nodes[..1], &nodes[1..]));
+        assert_canonical(&MetadataRequest::new(
+            &FleetStateChecksum::from_nodes(&nodes, None),
+            &nodes,
+        ));
+        assert_canonical(&MetadataResponse::new(
+            &ursula_signer,
+            &MetadataResponsePayload::new(2, &nodes),
+        ));
+
+        let dkg_pk = DkgPublicKey::random();
+        let (ciphertext, auth_data) = encrypt_for_dkg(b"message", &dkg_pk, &conditions).unwrap();
+        let acp = AccessControlPolicy::new(&auth_data, b"authorization");
+        assert_canonical(&auth_data);
+        assert_canonical(&acp);
+        assert_canonical(&ThresholdMessageKit::new(&ciphertext, &acp));
+    }
+
+    #[test]
+    fn detached_signature() {
+        let signer = Signer::new(SecretKey::random());
+        let hrac = HRAC::from([1; HRAC::SIZE]);
+        let kit = RetrievalKit::from_message_kit(&MessageKit::new(
+            &SecretKey::random().public_key(),
+            b"message",
+            None,
+        ));
+
+        let signature = sign_detached(&signer, &kit);
+        assert!(verify_detached(&kit, &signature, &signer.verifying_key()));
+        assert!(!verify_detached(
+            &kit,
+            &signature,
+            &SecretKey::random().public_key()
+        ));
+        assert!(!verify_detached(
+            &kit.with_hrac(&hrac),
+            &signature,
+            &signer.verifying_key()
+        ));
+
+        // Works with trait objects too
+        let object: Box<dyn super::ErasedProtocolObject> =
+            Box::new(RetrievalKit::from_message_kit(&MessageKit::new(
+                &SecretKey::random().public_key(),
+                b"message",
+                None,
+            )));
+        let signature = sign_detached(&signer, object.as_ref());
+        assert!(verify_detached(
+            object.as_ref(),
+            &signature,
+            &signer.verifying_key()
+        ));
+    }
+
+    #[test]
+    fn struct_encoding() {
+        #[derive(Serialize, Deserialize, Debug, PartialEq)]
+        struct Old {
+            a: u8,
+        }
+
+        #[derive(Serialize, Deserialize, Debug, PartialEq)]
+        struct New {
+            a: u8,
+            #[serde(default)]
+            b: Option<u8>,
+        }
+
+        let old = Old { a: 1 };
+        let new = New { a: 1, b: Some(2) };
+
+        // Old objects can be read by the new code with either encoding
+        for encoding in [StructEncoding::Array, StructEncoding::Map] {
+            let bytes = messagepack_serialize_with(&old, encoding);
+            assert_eq!(
+                messagepack_deserialize::<New>(&bytes),
+                Ok(New { a: 1, b: None })
+            );
         }
 
-        let result = match Self::unversioned_from_bytes(header.minor_version, &bytes[8..]) {
-            Some(result) => result,
-            // The type must support all minor versions below or equal to the current one,
-            // otherwise it should be the major version change.
-            // This is a bug, so we panic here.
-            None => panic!("minor version {} is not supported", header.minor_version),
-        };
+        // But only the map encoding allows the old code to read new objects
+        let bytes = messagepack_serialize_with(&new, StructEncoding::Map);
+        assert_eq!(messagepack_deserialize::<Old>(&bytes), Ok(Old { a: 1 }));
+        assert_eq!(
+            messagepack_deserialize::<New>(&bytes),
+            Ok(New { a: 1, b: Some(2) })
+        );
 
-        result.map_err(|msg| DeserializationError::BadPayload { error_msg: msg })
+        assert_eq!(
+            messagepack_serialize(&new),
+            messagepack_serialize_with(&new, StructEncoding::Array)
+        );
+        assert!(messagepack_serialize(&new).len() < bytes.len());
     }
 }